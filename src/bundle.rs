@@ -0,0 +1,259 @@
+use std::fmt;
+
+use burn::{
+    config::{Config, ConfigError},
+    module::Module,
+    prelude::Backend,
+    record::{FullPrecisionSettings, NamedMpkFileRecorder, Recorder, RecorderError},
+};
+
+use crate::{
+    calibration::Calibrator,
+    classifier::{Classifier, ClassifierConfig, ModelKind},
+    data::{Pipeline, TestDataSet, TitanicRecord},
+    model::{values, Model, ModelConfig, ModelRecord},
+    training::ExpConfig,
+};
+
+#[derive(Config)]
+pub struct TrainingMetadata {
+    pub train_path: String,
+    pub valid_path: String,
+    pub train_rows: usize,
+    pub epochs: usize,
+    pub valid_loss: f64,
+    pub valid_accuracy: f64,
+    // Epoch whose weights were kept when early stopping restored the best one.
+    pub best_epoch: Option<usize>,
+}
+
+/// Everything needed to rebuild a trained model: the weights live in
+/// `<path>.mpk` and this bundle in `<path>.json`.
+#[derive(Config)]
+pub struct ModelBundle {
+    #[config(default = "ModelKind::NeuralNet")]
+    pub kind: ModelKind,
+    pub model: ModelConfig,
+    pub features: Vec<String>,
+    pub pipeline: Pipeline,
+    pub experiment: ExpConfig,
+    pub metadata: TrainingMetadata,
+    // Maps the model's probabilities to calibrated ones, fitted by `calibrate`.
+    pub calibration: Option<Calibrator>,
+
+    // Decision threshold tuned by `tune-threshold`, 0.5 when unset.
+    pub threshold: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Config(ConfigError),
+    Weights(RecorderError),
+    Incompatible(String),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(err) => write!(f, "could not read bundle: {}", err),
+            Self::Weights(err) => write!(f, "could not read weights: {}", err),
+            Self::Incompatible(reason) => write!(f, "incompatible bundle: {}", reason),
+        }
+    }
+}
+
+pub fn bundle_path(model_path: &str) -> String {
+    format!("{}.json", model_path)
+}
+
+pub fn create_parent(model_path: &str) {
+    if let Some(parent) = std::path::Path::new(model_path).parent() {
+        std::fs::create_dir_all(parent).expect("could not create model directory");
+    }
+}
+
+impl ModelBundle {
+    pub fn save_model<B: Backend>(&self, model: Model<B>, model_path: &str) {
+        create_parent(model_path);
+
+        let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+        model
+            .save_file(model_path, &recorder)
+            .expect("could not save model");
+        self.save(bundle_path(model_path))
+            .expect("could not save model bundle");
+    }
+
+    pub fn load_model<B: Backend>(
+        model_path: &str,
+        device: &B::Device,
+    ) -> Result<(ModelBundle, Model<B>), BundleError> {
+        let bundle = ModelBundle::read(&bundle_path(model_path)).map_err(BundleError::Config)?;
+        bundle.check()?;
+
+        let record: ModelRecord<B> = NamedMpkFileRecorder::<FullPrecisionSettings>::new()
+            .load(model_path.into(), device)
+            .map_err(BundleError::Weights)?;
+        let model = bundle.model.init(device).load_record(record);
+        model
+            .check(&bundle.model)
+            .map_err(BundleError::Incompatible)?;
+
+        Ok((bundle, model))
+    }
+
+    /// Like `load`, but fills in the kind of bundles saved before the other model kinds existed.
+    fn read(path: &str) -> Result<ModelBundle, ConfigError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|_| ConfigError::FileNotFound(path.to_string()))?;
+        let mut value: serde_json::Value = serde_json::from_str(&json)
+            .map_err(|err| ConfigError::InvalidFormat(err.to_string()))?;
+        if let Some(fields) = value.as_object_mut() {
+            fields
+                .entry("kind")
+                .or_insert_with(|| serde_json::json!(ModelKind::default()));
+        }
+        serde_json::from_value(value).map_err(|err| ConfigError::InvalidFormat(err.to_string()))
+    }
+
+    fn check(&self) -> Result<(), BundleError> {
+        let expected = self.pipeline.names();
+        if self.features != expected {
+            return Err(BundleError::Incompatible(format!(
+                "model was trained on features {:?} but this build extracts {:?}",
+                self.features, expected
+            )));
+        }
+        if self.pipeline.extractor.config != self.model.features {
+            return Err(BundleError::Incompatible(
+                "the fitted feature extractor does not match the model's feature config"
+                    .to_string(),
+            ));
+        }
+        if self.pipeline.preprocessor.columns.len() != self.features.len() {
+            return Err(BundleError::Incompatible(format!(
+                "preprocessor scales {} columns but the bundle lists {} features",
+                self.pipeline.preprocessor.columns.len(),
+                self.features.len()
+            )));
+        }
+        if self.pipeline.encoder.columns != self.model.embedded()
+            || self.pipeline.encoder.vocab_sizes()
+                != self
+                    .model
+                    .embeddings
+                    .iter()
+                    .map(|e| e.vocab_size)
+                    .collect::<Vec<_>>()
+        {
+            return Err(BundleError::Incompatible(
+                "the fitted category vocabularies do not match the model's embeddings".to_string(),
+            ));
+        }
+        if self.model.feature_size != self.features.len() {
+            return Err(BundleError::Incompatible(format!(
+                "feature_size is {} but the bundle lists {} features",
+                self.model.feature_size,
+                self.features.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A loaded neural network bundle with its weights.
+pub struct NeuralNet<B: Backend> {
+    pub bundle: ModelBundle,
+    pub model: Model<B>,
+    pub device: B::Device,
+}
+
+impl<B: Backend> NeuralNet<B> {
+    pub fn load(model_path: &str, device: &B::Device) -> Result<NeuralNet<B>, BundleError> {
+        let (bundle, model) = ModelBundle::load_model(model_path, device)?;
+        Ok(NeuralNet {
+            bundle,
+            model,
+            device: device.clone(),
+        })
+    }
+}
+
+impl<B: Backend> Classifier for NeuralNet<B> {
+    fn kind(&self) -> ModelKind {
+        ModelKind::NeuralNet
+    }
+
+    fn metadata(&self) -> &TrainingMetadata {
+        &self.bundle.metadata
+    }
+
+    fn config(&self) -> ClassifierConfig {
+        ClassifierConfig::NeuralNet {
+            experiment: self.bundle.experiment.clone(),
+            model: self.bundle.model.clone(),
+        }
+    }
+
+    fn calibration(&self) -> Option<&Calibrator> {
+        self.bundle.calibration.as_ref()
+    }
+
+    fn set_calibration(&mut self, calibrator: Option<Calibrator>) {
+        self.bundle.calibration = calibrator;
+    }
+
+    fn threshold(&self) -> Option<f32> {
+        self.bundle.threshold
+    }
+
+    fn set_threshold(&mut self, threshold: Option<f32>) {
+        self.bundle.threshold = threshold;
+    }
+
+    fn raw_proba(&self, records: &[TitanicRecord]) -> Vec<f32> {
+        let batch = TestDataSet::from_records(records, &self.bundle.pipeline).batch(&self.device);
+        values(self.model.probabilities(batch.inputs, batch.categories))
+    }
+
+    fn save(&self, model_path: &str) {
+        self.bundle.save_model(self.model.clone(), model_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{FeatureConfig, ImputeConfig, PreprocessConfig};
+
+    #[test]
+    fn reads_bundles_saved_without_a_kind_as_neural_networks() {
+        let records: Vec<TitanicRecord> = (0..4)
+            .map(|id| TitanicRecord::example(id, (id % 2) as u8))
+            .collect();
+        let pipeline = Pipeline::fit(
+            &FeatureConfig::new(),
+            &[],
+            &ImputeConfig::new(),
+            &PreprocessConfig::new(),
+            &records,
+        );
+        let metadata =
+            TrainingMetadata::new("train.csv".into(), "valid.csv".into(), 4, 1, 0.6, 0.5);
+        let bundle = ModelBundle::new(
+            ModelConfig::new(),
+            pipeline.names(),
+            pipeline,
+            ExpConfig::new(),
+            metadata,
+        );
+        let mut value = serde_json::to_value(&bundle).unwrap();
+        value.as_object_mut().unwrap().remove("kind");
+
+        let path = std::env::temp_dir().join(format!("titanic-bundle-{}.json", std::process::id()));
+        std::fs::write(&path, value.to_string()).unwrap();
+        let read = ModelBundle::read(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap().kind, ModelKind::NeuralNet);
+    }
+}
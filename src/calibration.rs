@@ -0,0 +1,380 @@
+use std::{path::Path, str::FromStr};
+
+use burn::{config::Config, tensor::backend::AutodiffBackend};
+use plotly::{common::Mode, layout::Axis, Layout, Plot, Scatter};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    classifier::{self, validation_records},
+    data::RawData,
+    metrics::{expected_calibration_error, reliability, Metrics},
+    model::DEFAULT_THRESHOLD,
+};
+
+// Newton steps of the Platt and temperature fits, which converge in a handful.
+const NEWTON_STEPS: usize = 100;
+
+pub fn logit(p: f32) -> f64 {
+    let p = (p as f64).clamp(1e-6, 1.0 - 1e-6);
+    (p / (1.0 - p)).ln()
+}
+
+pub fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[derive(Config, Debug, PartialEq)]
+pub enum CalibrationMethod {
+    Platt,
+    Temperature,
+    Isotonic,
+}
+
+impl FromStr for CalibrationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "platt" => Ok(CalibrationMethod::Platt),
+            "temperature" => Ok(CalibrationMethod::Temperature),
+            "isotonic" => Ok(CalibrationMethod::Isotonic),
+            _ => Err(format!(
+                "unknown calibration method {}, expected platt, temperature or isotonic",
+                s
+            )),
+        }
+    }
+}
+
+/// Maps a model's survival probabilities to calibrated ones. Saved in the model bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Calibrator {
+    // sigmoid(a * logit(p) + b)
+    Platt {
+        a: f64,
+        b: f64,
+    },
+    // sigmoid(logit(p) / temperature), which keeps the ranking and the 0.5 crossing.
+    Temperature {
+        temperature: f64,
+    },
+    // Survival rate of every block of the isotonic fit at its lowest and highest probability,
+    // flat within a block and beyond the first and last, linear between blocks.
+    Isotonic {
+        probabilities: Vec<f32>,
+        rates: Vec<f32>,
+    },
+}
+
+impl Calibrator {
+    pub fn fit(method: &CalibrationMethod, probabilities: &[f32], labels: &[u8]) -> Calibrator {
+        assert!(
+            !probabilities.is_empty(),
+            "the calibration set must have at least one labeled record"
+        );
+        match method {
+            CalibrationMethod::Platt => platt(probabilities, labels),
+            CalibrationMethod::Temperature => temperature(probabilities, labels),
+            CalibrationMethod::Isotonic => isotonic(probabilities, labels),
+        }
+    }
+
+    pub fn apply(&self, p: f32) -> f32 {
+        match self {
+            Calibrator::Platt { a, b } => sigmoid(a * logit(p) + b) as f32,
+            Calibrator::Temperature { temperature } => sigmoid(logit(p) / temperature) as f32,
+            Calibrator::Isotonic {
+                probabilities,
+                rates,
+            } => {
+                let i = probabilities.partition_point(|x| *x < p);
+                if i == 0 {
+                    return rates[0];
+                }
+                if i == probabilities.len() {
+                    return rates[i - 1];
+                }
+                let (x0, x1) = (probabilities[i - 1], probabilities[i]);
+                let (y0, y1) = (rates[i - 1], rates[i]);
+                if x1 > x0 {
+                    y0 + (y1 - y0) * (p - x0) / (x1 - x0)
+                } else {
+                    y1
+                }
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Calibrator::Platt { a, b } => format!("Platt scaling: a {:.3}, b {:.3}", a, b),
+            Calibrator::Temperature { temperature } => {
+                format!("Temperature scaling: T {:.3}", temperature)
+            }
+            Calibrator::Isotonic { probabilities, .. } => {
+                format!("Isotonic regression: {} steps", probabilities.len() / 2)
+            }
+        }
+    }
+}
+
+/// Logistic regression on the log-odds, by Newton's method. The labels are smoothed to
+/// `(N+ + 1) / (N+ + 2)` and `1 / (N- + 2)` as in Platt's paper, so separable data still
+/// gives finite coefficients.
+fn platt(probabilities: &[f32], labels: &[u8]) -> Calibrator {
+    let positives = labels.iter().filter(|l| **l == 1).count() as f64;
+    let negatives = labels.len() as f64 - positives;
+    let (high, low) = (
+        (positives + 1.0) / (positives + 2.0),
+        1.0 / (negatives + 2.0),
+    );
+
+    let (mut a, mut b) = (1.0, 0.0);
+    for _ in 0..NEWTON_STEPS {
+        let (mut ga, mut gb, mut haa, mut hab, mut hbb) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (p, label) in probabilities.iter().zip(labels) {
+            let z = logit(*p);
+            let q = sigmoid(a * z + b);
+            let target = if *label == 1 { high } else { low };
+            let w = (q * (1.0 - q)).max(1e-12);
+            ga += (q - target) * z;
+            gb += q - target;
+            haa += w * z * z;
+            hab += w * z;
+            hbb += w;
+        }
+        let det = haa * hbb - hab * hab;
+        if det.abs() < 1e-12 {
+            break;
+        }
+        let (da, db) = ((hbb * ga - hab * gb) / det, (haa * gb - hab * ga) / det);
+        a -= da;
+        b -= db;
+        if da.abs() + db.abs() < 1e-9 {
+            break;
+        }
+    }
+    Calibrator::Platt { a, b }
+}
+
+/// A single scale on the log-odds, by Newton's method on its inverse.
+fn temperature(probabilities: &[f32], labels: &[u8]) -> Calibrator {
+    let mut scale: f64 = 1.0;
+    for _ in 0..NEWTON_STEPS {
+        let (mut gradient, mut hessian) = (0.0, 0.0);
+        for (p, label) in probabilities.iter().zip(labels) {
+            let z = logit(*p);
+            let q = sigmoid(scale * z);
+            gradient += (q - *label as f64) * z;
+            hessian += q * (1.0 - q) * z * z;
+        }
+        if hessian < 1e-12 {
+            break;
+        }
+        let step = gradient / hessian;
+        scale = (scale - step).clamp(1e-3, 1e3);
+        if step.abs() < 1e-9 {
+            break;
+        }
+    }
+    Calibrator::Temperature {
+        temperature: 1.0 / scale,
+    }
+}
+
+/// Pool adjacent violators: merges neighbouring blocks, in order of probability, until the
+/// survival rate never decreases.
+fn isotonic(probabilities: &[f32], labels: &[u8]) -> Calibrator {
+    let mut points: Vec<(f32, u8)> = probabilities
+        .iter()
+        .copied()
+        .zip(labels.iter().copied())
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // (survivors, rows, lowest probability, highest probability)
+    let mut blocks: Vec<(f64, usize, f32, f32)> = Vec::new();
+    for (p, label) in points {
+        blocks.push((label as f64, 1, p, p));
+        while blocks.len() > 1 {
+            let (last, previous) = (blocks[blocks.len() - 1], blocks[blocks.len() - 2]);
+            if previous.0 / previous.1 as f64 <= last.0 / last.1 as f64 {
+                break;
+            }
+            blocks.pop();
+            let merged = blocks.last_mut().unwrap();
+            merged.0 += last.0;
+            merged.1 += last.1;
+            merged.3 = last.3;
+        }
+    }
+
+    let mut calibrated = (Vec::new(), Vec::new());
+    for (survived, rows, lowest, highest) in blocks {
+        let rate = (survived / rows as f64) as f32;
+        calibrated.0.extend([lowest, highest]);
+        calibrated.1.extend([rate, rate]);
+    }
+    Calibrator::Isotonic {
+        probabilities: calibrated.0,
+        rates: calibrated.1,
+    }
+}
+
+/// Reliability diagram: the survival rate against the mean probability in every bin, one line
+/// per set of probabilities.
+pub fn plot_reliability(curves: &[(&str, &[f32])], labels: &[u8], bins: usize, output_path: &Path) {
+    let mut plot = Plot::new();
+    plot.add_trace(
+        Scatter::new(vec![0.0, 1.0], vec![0.0, 1.0])
+            .name("Perfectly calibrated")
+            .mode(Mode::Lines),
+    );
+    for (name, probabilities) in curves {
+        let (x, y): (Vec<f64>, Vec<f64>) = reliability(probabilities, labels, bins)
+            .iter()
+            .map(|bin| (bin.mean_probability, bin.survival_rate))
+            .unzip();
+        let ece = expected_calibration_error(probabilities, labels, bins);
+        plot.add_trace(
+            Scatter::new(x, y)
+                .name(format!("{} (ECE {:.3})", name, ece).as_str())
+                .mode(Mode::LinesMarkers),
+        );
+    }
+
+    let layout = Layout::new()
+        .title("Reliability diagram")
+        .x_axis(Axis::new().title("Mean predicted probability"))
+        .y_axis(Axis::new().title("Survival rate"));
+    plot.set_layout(layout);
+
+    plot.write_html(output_path);
+}
+
+/// Fits a calibrator on the validation data, the model's own validation split unless
+/// `valid_path` is given, charts the reliability before and after, and saves it with the model.
+pub fn run<B: AutodiffBackend>(
+    model_path: &str,
+    method: &CalibrationMethod,
+    valid_path: Option<&str>,
+    bins: usize,
+    chart_path: &str,
+    device: B::Device,
+) {
+    let mut model = classifier::load::<B>(model_path, &device);
+    model.describe();
+
+    let records = match valid_path {
+        Some(path) => RawData::new(path).get_all_rows().clone(),
+        None => validation_records(model.as_ref()),
+    };
+    let labels: Vec<u8> = records
+        .iter()
+        .map(|record| {
+            record
+                .survived
+                .expect("calibration data must have a Survived column")
+        })
+        .collect();
+
+    let uncalibrated = model.raw_proba(&records);
+    let calibrator = Calibrator::fit(method, &uncalibrated, &labels);
+    let calibrated: Vec<f32> = uncalibrated.iter().map(|p| calibrator.apply(*p)).collect();
+    println!("{}", calibrator.describe());
+
+    for (name, probabilities) in [("uncalibrated", &uncalibrated), ("calibrated", &calibrated)] {
+        let metrics = Metrics::new(probabilities, &labels, DEFAULT_THRESHOLD);
+        println!(
+            "*** [Calibrate - {}] Loss {:.3} | Brier {:.3} | ECE {:.3}",
+            name,
+            metrics.log_loss,
+            metrics.brier,
+            expected_calibration_error(probabilities, &labels, bins)
+        );
+    }
+    plot_reliability(
+        &[("Uncalibrated", &uncalibrated), ("Calibrated", &calibrated)],
+        &labels,
+        bins,
+        Path::new(chart_path),
+    );
+
+    model.set_calibration(Some(calibrator));
+    if model.threshold().is_some() {
+        println!("The tuned threshold no longer applies and was removed, tune it again");
+        model.set_threshold(None);
+    }
+    model.save(model_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "the calibration set must have at least one labeled record")]
+    fn rejects_an_empty_calibration_set() {
+        Calibrator::fit(&CalibrationMethod::Isotonic, &[], &[]);
+    }
+
+    #[test]
+    fn isotonic_pools_adjacent_violators() {
+        let calibrator = isotonic(&[0.1, 0.2, 0.3, 0.4], &[0, 1, 0, 1]);
+        match &calibrator {
+            Calibrator::Isotonic {
+                probabilities,
+                rates,
+            } => {
+                assert_eq!(probabilities, &vec![0.1, 0.1, 0.2, 0.3, 0.4, 0.4]);
+                assert_eq!(rates, &vec![0.0, 0.0, 0.5, 0.5, 1.0, 1.0]);
+            }
+            _ => panic!("expected an isotonic calibrator"),
+        }
+        assert_eq!(calibrator.apply(0.0), 0.0);
+        assert_eq!(calibrator.apply(0.25), 0.5);
+        assert!((calibrator.apply(0.35) - 0.75).abs() < 1e-6);
+        assert_eq!(calibrator.apply(0.9), 1.0);
+    }
+
+    #[test]
+    fn isotonic_never_decreases() {
+        let probabilities: Vec<f32> = (0..50).map(|i| i as f32 / 50.0).collect();
+        let labels: Vec<u8> = (0..50).map(|i| ((i * 7) % 5 < i / 10) as u8).collect();
+        let calibrator = isotonic(&probabilities, &labels);
+        let calibrated: Vec<f32> = (0..=100)
+            .map(|i| calibrator.apply(i as f32 / 100.0))
+            .collect();
+        assert!(calibrated.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn platt_stays_finite_on_separable_data() {
+        let calibrator = platt(&[0.1, 0.2, 0.8, 0.9], &[0, 0, 1, 1]);
+        let Calibrator::Platt { a, b } = calibrator else {
+            panic!("expected a Platt calibrator");
+        };
+        assert!(a.is_finite() && b.is_finite() && a > 0.0);
+        assert!(calibrator.apply(0.1) < 0.5 && calibrator.apply(0.9) > 0.5);
+    }
+
+    #[test]
+    fn temperature_softens_an_overconfident_model() {
+        // right three times out of four at 90% confidence
+        let probabilities = [0.9, 0.9, 0.9, 0.9, 0.1, 0.1, 0.1, 0.1];
+        let labels = [1, 1, 1, 0, 0, 0, 0, 1];
+        let calibrator = temperature(&probabilities, &labels);
+        let Calibrator::Temperature { temperature } = calibrator else {
+            panic!("expected a temperature calibrator");
+        };
+        assert!(temperature > 1.0);
+        assert!((calibrator.apply(0.9) - 0.75).abs() < 1e-4);
+        assert!((calibrator.apply(0.5) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parses_methods() {
+        assert_eq!("isotonic".parse(), Ok(CalibrationMethod::Isotonic));
+        assert!("beta".parse::<CalibrationMethod>().is_err());
+    }
+}
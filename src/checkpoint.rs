@@ -0,0 +1,231 @@
+use std::{path::PathBuf, str::FromStr};
+
+use burn::{
+    config::Config,
+    module::Module,
+    optim::Optimizer,
+    prelude::Backend,
+    record::{FullPrecisionSettings, NamedMpkFileRecorder, Recorder},
+    tensor::backend::AutodiffBackend,
+};
+
+use crate::{metrics::Metrics, model::Model, scheduler::SchedulerState};
+
+/// Validation value early stopping watches.
+#[derive(Config, Debug, PartialEq)]
+pub enum Monitor {
+    ValidLoss,
+    ValidAccuracy,
+    ValidF1,
+    ValidRocAuc,
+}
+
+impl FromStr for Monitor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "valid-loss" => Ok(Monitor::ValidLoss),
+            "valid-accuracy" => Ok(Monitor::ValidAccuracy),
+            "valid-f1" => Ok(Monitor::ValidF1),
+            "valid-roc-auc" => Ok(Monitor::ValidRocAuc),
+            _ => Err(format!(
+                "unknown monitor {}, expected valid-loss, valid-accuracy, valid-f1 or valid-roc-auc",
+                s
+            )),
+        }
+    }
+}
+
+impl Monitor {
+    pub fn value(&self, loss: f64, metrics: &Metrics) -> f64 {
+        match self {
+            Monitor::ValidLoss => loss,
+            Monitor::ValidAccuracy => metrics.accuracy,
+            Monitor::ValidF1 => metrics.f1,
+            Monitor::ValidRocAuc => metrics.roc_auc,
+        }
+    }
+
+    fn minimize(&self) -> bool {
+        *self == Monitor::ValidLoss
+    }
+}
+
+#[derive(Config, Debug)]
+pub struct EarlyStoppingConfig {
+    #[config(default = "Monitor::ValidLoss")]
+    pub monitor: Monitor,
+
+    // Epochs without improvement before training stops.
+    #[config(default = 5)]
+    pub patience: usize,
+
+    // Smallest change of the monitored value that counts as an improvement.
+    #[config(default = 0.0)]
+    pub min_delta: f64,
+
+    // Keep the weights of the best epoch instead of the last one.
+    #[config(default = true)]
+    pub restore_best: bool,
+}
+
+impl EarlyStoppingConfig {
+    pub fn improved(&self, value: f64, best: Option<f64>) -> bool {
+        match best {
+            None => true,
+            Some(best) if self.monitor.minimize() => value < best - self.min_delta,
+            Some(best) => value > best + self.min_delta,
+        }
+    }
+}
+
+/// Where a run stands after an epoch, written next to the checkpoints so it can be resumed.
+#[derive(Config, Debug)]
+pub struct TrainingState {
+    pub epoch: usize,
+    pub best_epoch: Option<usize>,
+    pub best_value: Option<f64>,
+
+    #[config(default = 0)]
+    pub stale_epochs: usize,
+
+    pub scheduler: Option<SchedulerState>,
+}
+
+/// Per-epoch model and optimizer checkpoints in `<model>.checkpoints/`.
+pub struct Checkpointer {
+    dir: PathBuf,
+    pub resume: bool,
+}
+
+impl Checkpointer {
+    pub fn new(model_path: &str, resume: bool) -> Checkpointer {
+        Checkpointer {
+            dir: PathBuf::from(format!("{}.checkpoints", model_path)),
+            resume,
+        }
+    }
+
+    fn path(&self, name: &str, epoch: usize) -> PathBuf {
+        self.dir.join(format!("{}-{}", name, epoch))
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+
+    /// Removes the checkpoints of an earlier run.
+    pub fn clear(&self) {
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir).expect("could not remove old checkpoints");
+        }
+    }
+
+    pub fn save<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+        &self,
+        model: &Model<B>,
+        optim: &O,
+        state: &TrainingState,
+    ) {
+        std::fs::create_dir_all(&self.dir).expect("could not create checkpoint directory");
+
+        let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+        model
+            .clone()
+            .save_file(self.path("model", state.epoch), &recorder)
+            .expect("could not save model checkpoint");
+        recorder
+            .record(optim.to_record(), self.path("optim", state.epoch))
+            .expect("could not save optimizer checkpoint");
+        state
+            .save(self.state_path())
+            .expect("could not save training state");
+    }
+
+    /// State of the last checkpointed epoch, if any.
+    pub fn state(&self) -> Option<TrainingState> {
+        TrainingState::load(self.state_path()).ok()
+    }
+
+    pub fn load_model<B: Backend>(
+        &self,
+        epoch: usize,
+        model: Model<B>,
+        device: &B::Device,
+    ) -> Model<B> {
+        model
+            .load_file(
+                self.path("model", epoch),
+                &NamedMpkFileRecorder::<FullPrecisionSettings>::new(),
+                device,
+            )
+            .expect("could not load model checkpoint")
+    }
+
+    pub fn load_optimizer<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+        &self,
+        epoch: usize,
+        optim: O,
+        device: &B::Device,
+    ) -> O {
+        let record = NamedMpkFileRecorder::<FullPrecisionSettings>::new()
+            .load(self.path("optim", epoch), device)
+            .expect("could not load optimizer checkpoint");
+        optim.load_record(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn losses_improve_downwards_by_more_than_min_delta() {
+        let config = EarlyStoppingConfig::new().with_min_delta(0.01);
+        assert!(config.improved(0.5, None));
+        assert!(config.improved(0.48, Some(0.5)));
+        assert!(!config.improved(0.495, Some(0.5)));
+        assert!(!config.improved(0.6, Some(0.5)));
+    }
+
+    #[test]
+    fn scores_improve_upwards_by_more_than_min_delta() {
+        let config = EarlyStoppingConfig::new()
+            .with_monitor(Monitor::ValidAccuracy)
+            .with_min_delta(0.01);
+        assert!(config.improved(0.82, Some(0.8)));
+        assert!(!config.improved(0.805, Some(0.8)));
+        assert!(!config.improved(0.7, Some(0.8)));
+    }
+
+    #[test]
+    fn resumes_the_saved_state() {
+        let dir = std::env::temp_dir().join(format!("titanic-checkpoint-{}", std::process::id()));
+        let checkpointer = Checkpointer::new(dir.join("model").to_str().unwrap(), true);
+        assert!(checkpointer.state().is_none());
+
+        let state = TrainingState::new(4)
+            .with_best_epoch(Some(2))
+            .with_best_value(Some(0.41))
+            .with_stale_epochs(2)
+            .with_scheduler(Some(
+                SchedulerState::new()
+                    .with_scale(0.5)
+                    .with_best_loss(Some(0.41)),
+            ));
+        std::fs::create_dir_all(&checkpointer.dir).unwrap();
+        state.save(checkpointer.state_path()).unwrap();
+
+        let resumed = checkpointer.state().expect("state should be saved");
+        checkpointer.clear();
+        assert_eq!(resumed.epoch, 4);
+        assert_eq!(resumed.best_epoch, Some(2));
+        assert_eq!(resumed.best_value, Some(0.41));
+        assert_eq!(resumed.stale_epochs, 2);
+        let scheduler = resumed.scheduler.expect("scheduler state should be saved");
+        assert_eq!(scheduler.scale, 0.5);
+        assert_eq!(scheduler.best_loss, Some(0.41));
+        assert!(!checkpointer.dir.exists());
+    }
+}
@@ -0,0 +1,319 @@
+use std::str::FromStr;
+
+use burn::{config::Config, prelude::Backend, tensor::backend::AutodiffBackend};
+use serde::Deserialize;
+
+use crate::{
+    bundle::{bundle_path, NeuralNet, TrainingMetadata},
+    calibration::Calibrator,
+    data::{RawData, SplitConfig, TitanicRecord},
+    forest::{self, ForestConfig},
+    gbdt::{self, GbdtConfig},
+    model::{ModelConfig, Prediction, DEFAULT_THRESHOLD},
+    scheduler::Schedule,
+    training::ExpConfig,
+};
+
+/// Model families, saved as `kind` in every bundle so `load` knows which one to rebuild.
+#[derive(Config, Debug, PartialEq)]
+pub enum ModelKind {
+    NeuralNet,
+    Gbdt,
+    Forest,
+}
+
+impl FromStr for ModelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nn" => Ok(ModelKind::NeuralNet),
+            "gbdt" => Ok(ModelKind::Gbdt),
+            "forest" => Ok(ModelKind::Forest),
+            _ => Err(format!(
+                "unknown model kind {}, expected nn, gbdt or forest",
+                s
+            )),
+        }
+    }
+}
+
+/// Bundles without a kind predate the boosted trees and forests.
+impl Default for ModelKind {
+    fn default() -> Self {
+        ModelKind::NeuralNet
+    }
+}
+
+impl ModelKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModelKind::NeuralNet => "Neural network",
+            ModelKind::Gbdt => "Gradient-boosted trees",
+            ModelKind::Forest => "Random forest",
+        }
+    }
+
+    /// What `TrainingMetadata::epochs` counts for this kind.
+    fn rounds(&self) -> &'static str {
+        match self {
+            ModelKind::NeuralNet => "epochs",
+            ModelKind::Gbdt | ModelKind::Forest => "trees",
+        }
+    }
+
+    /// What `TrainingMetadata::best_epoch` refers to.
+    fn round(&self) -> &'static str {
+        match self {
+            ModelKind::NeuralNet => "epoch",
+            ModelKind::Gbdt => "round",
+            ModelKind::Forest => "tree",
+        }
+    }
+}
+
+/// A trained model that predicts survival from raw passenger records, applying its own
+/// fitted feature pipeline. Fitting goes through `ClassifierConfig::fit` and loading through
+/// `load`, which reads the kind saved in the bundle.
+pub trait Classifier {
+    fn kind(&self) -> ModelKind;
+
+    fn metadata(&self) -> &TrainingMetadata;
+
+    /// Settings to fit the same kind of model again, e.g. on cross-validation folds.
+    fn config(&self) -> ClassifierConfig;
+
+    /// Survival probability of every record, before calibration.
+    fn raw_proba(&self, records: &[TitanicRecord]) -> Vec<f32>;
+
+    fn calibration(&self) -> Option<&Calibrator>;
+
+    fn set_calibration(&mut self, calibrator: Option<Calibrator>);
+
+    /// Decision threshold tuned by `tune-threshold`, if any.
+    fn threshold(&self) -> Option<f32>;
+
+    fn set_threshold(&mut self, threshold: Option<f32>);
+
+    /// The tuned threshold, 0.5 when there is none.
+    fn decision_threshold(&self) -> f32 {
+        self.threshold().unwrap_or(DEFAULT_THRESHOLD)
+    }
+
+    /// Survival probability of every record, calibrated when the bundle has a calibrator.
+    fn predict_proba(&self, records: &[TitanicRecord]) -> Vec<f32> {
+        let probabilities = self.raw_proba(records);
+        match self.calibration() {
+            Some(calibrator) => probabilities.iter().map(|p| calibrator.apply(*p)).collect(),
+            None => probabilities,
+        }
+    }
+
+    fn predict(&self, records: &[TitanicRecord], threshold: f32) -> Vec<Prediction> {
+        records
+            .iter()
+            .zip(self.predict_proba(records))
+            .map(|(record, probability)| Prediction {
+                id: record.passenger_id,
+                probability,
+                survived: (probability >= threshold) as u8,
+            })
+            .collect()
+    }
+
+    /// Saves the bundle to `<model_path>.json`, plus any weights next to it.
+    fn save(&self, model_path: &str);
+
+    fn describe(&self) {
+        let metadata = self.metadata();
+        let mut calibrated = match self.calibration() {
+            Some(calibrator) => format!(", {}", calibrator.describe()),
+            None => String::new(),
+        };
+        if let Some(threshold) = self.threshold() {
+            calibrated.push_str(&format!(", threshold {:.3}", threshold));
+        }
+        let best = match metadata.best_epoch {
+            Some(epoch) => format!(", best {} {}", self.kind().round(), epoch),
+            None => String::new(),
+        };
+        println!(
+            "{} trained on {} ({} rows) with {} {}{}, validated on {}: Loss {:.3} | Accuracy {:.3} %{}",
+            self.kind().name(),
+            metadata.train_path,
+            metadata.train_rows,
+            metadata.epochs,
+            if metadata.epochs == 1 {
+                self.kind().round()
+            } else {
+                self.kind().rounds()
+            },
+            best,
+            metadata.valid_path,
+            metadata.valid_loss,
+            metadata.valid_accuracy,
+            calibrated,
+        );
+    }
+}
+
+/// Labeled records to fit on and to validate against, named for the bundle metadata.
+pub struct TrainingData {
+    pub records: Vec<TitanicRecord>,
+    pub valid_records: Vec<TitanicRecord>,
+    pub train_path: String,
+    pub valid_path: String,
+}
+
+impl TrainingData {
+    /// Reads the training csvs and either the validation csv or a stratified split of them.
+    pub fn load(
+        train_paths: &[String],
+        valid_path: &str,
+        split: Option<&SplitConfig>,
+        seed: u64,
+    ) -> TrainingData {
+        let raw_data = RawData::from_paths(train_paths);
+        let train_path = train_paths.join(",");
+        match split {
+            Some(split) => {
+                let (records, valid_records) = split.split(raw_data.get_all_rows(), seed);
+                let valid_path = format!(
+                    "{:.0}% stratified split of {} (seed {})",
+                    split.valid_ratio * 100.0,
+                    train_path,
+                    seed
+                );
+                TrainingData {
+                    records,
+                    valid_records,
+                    train_path,
+                    valid_path,
+                }
+            }
+            None => TrainingData {
+                records: raw_data.get_all_rows().clone(),
+                valid_records: RawData::new(valid_path).get_all_rows().clone(),
+                train_path,
+                valid_path: valid_path.to_string(),
+            },
+        }
+    }
+}
+
+/// What to train, with the settings of the chosen model kind.
+#[derive(Clone)]
+pub enum ClassifierConfig {
+    NeuralNet {
+        experiment: ExpConfig,
+        model: ModelConfig,
+    },
+    Gbdt(GbdtConfig),
+    Forest(ForestConfig),
+}
+
+impl ClassifierConfig {
+    pub fn split(&self) -> Option<&SplitConfig> {
+        match self {
+            ClassifierConfig::NeuralNet { experiment, .. } => experiment.split.as_ref(),
+            ClassifierConfig::Gbdt(config) => config.split.as_ref(),
+            ClassifierConfig::Forest(config) => config.split.as_ref(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        match self {
+            ClassifierConfig::NeuralNet { experiment, .. } => experiment.seed,
+            ClassifierConfig::Gbdt(config) => config.seed,
+            ClassifierConfig::Forest(config) => config.seed,
+        }
+    }
+
+    /// Whether fitting watches the validation records, for early stopping or the plateau
+    /// schedule, instead of only reporting on them.
+    pub fn watches_validation(&self) -> bool {
+        match self {
+            ClassifierConfig::NeuralNet { experiment, .. } => {
+                experiment.early_stopping.is_some()
+                    || matches!(experiment.scheduler.schedule, Schedule::Plateau { .. })
+            }
+            ClassifierConfig::Gbdt(config) => config.early_stopping_rounds > 0,
+            ClassifierConfig::Forest(_) => false,
+        }
+    }
+
+    /// Fits a fresh model on `data.records`, validating on `data.valid_records`.
+    pub fn fit<B: AutodiffBackend>(
+        &self,
+        data: &TrainingData,
+        verbose: bool,
+        device: &B::Device,
+    ) -> Box<dyn Classifier> {
+        match self {
+            ClassifierConfig::NeuralNet { experiment, model } => Box::new(NeuralNet::<B>::fit(
+                data,
+                experiment.clone(),
+                model.clone(),
+                verbose,
+                None,
+                device,
+            )),
+            ClassifierConfig::Gbdt(config) => Box::new(gbdt::fit_bundle(data, config, verbose)),
+            ClassifierConfig::Forest(config) => Box::new(forest::fit_bundle(data, config, verbose)),
+        }
+    }
+}
+
+/// Trains on `train_paths`, validating on `valid_path` unless the config splits the training
+/// data, and saves the model to `model_path`.
+pub fn train<B: AutodiffBackend>(
+    model_path: &str,
+    train_paths: &[String],
+    valid_path: &str,
+    config: &ClassifierConfig,
+    device: &B::Device,
+) {
+    let data = TrainingData::load(train_paths, valid_path, config.split(), config.seed());
+    config.fit::<B>(&data, true, device).save(model_path);
+}
+
+/// The records a saved model was validated on: its validation csv, or the stratified split of
+/// its training csvs rebuilt from the saved split settings and seed.
+pub fn validation_records(model: &dyn Classifier) -> Vec<TitanicRecord> {
+    let config = model.config();
+    let metadata = model.metadata();
+    match config.split() {
+        Some(split) => {
+            let train_paths: Vec<String> = metadata
+                .train_path
+                .split(',')
+                .map(|path| path.to_string())
+                .collect();
+            TrainingData::load(&train_paths, "", Some(split), config.seed()).valid_records
+        }
+        None => RawData::new(&metadata.valid_path).get_all_rows().clone(),
+    }
+}
+
+#[derive(Deserialize)]
+struct Tagged {
+    #[serde(default)]
+    kind: ModelKind,
+}
+
+/// Loads a saved model of any kind.
+pub fn load<B: Backend>(model_path: &str, device: &B::Device) -> Box<dyn Classifier> {
+    let json = std::fs::read_to_string(bundle_path(model_path))
+        .unwrap_or_else(|err| panic!("could not load model {}: {}", model_path, err));
+    let tagged: Tagged = serde_json::from_str(&json)
+        .unwrap_or_else(|err| panic!("could not read the kind of model {}: {}", model_path, err));
+
+    match tagged.kind {
+        ModelKind::NeuralNet => Box::new(
+            NeuralNet::<B>::load(model_path, device)
+                .unwrap_or_else(|err| panic!("could not load model {}: {}", model_path, err)),
+        ),
+        ModelKind::Gbdt => Box::new(gbdt::load(model_path)),
+        ModelKind::Forest => Box::new(forest::load(model_path)),
+    }
+}
@@ -0,0 +1,1170 @@
+use burn::config::Config;
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    calibration::CalibrationMethod,
+    checkpoint::{EarlyStoppingConfig, Monitor},
+    classifier::{ClassifierConfig, ModelKind},
+    cv::CvConfig,
+    data::{FeatureConfig, ImputeConfig, ImputeStrategy, PreprocessConfig, Scaler, SplitConfig},
+    ensemble::{Combiner, EnsembleConfig},
+    forest::ForestConfig,
+    gbdt::GbdtConfig,
+    lr_find::LrFindConfig,
+    metrics::CALIBRATION_BINS,
+    model::{Activation, EmbeddingConfig, LayerConfig, ModelConfig, Norm},
+    optimizer::OptimizerKind,
+    scheduler::Schedule,
+    search::{SearchConfig, Strategy},
+    threshold::Objective,
+    training::ExpConfig,
+    tree::{Criterion, MaxFeatures, TreeConfig},
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "titanic", about = "Titanic survival classifier")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Train a model and save its weights
+    Train(TrainArgs),
+    /// Predict survival for an unlabeled csv with a saved model of any kind and write a
+    /// submission file
+    Infer(InferArgs),
+    /// Train gradient-boosted trees on the same features
+    TrainGbdt(TrainGbdtArgs),
+    /// Train a single CART decision tree and print it as rules
+    TrainTree(TrainTreeArgs),
+    /// Train a random forest of bagged decision trees
+    TrainForest(TrainForestArgs),
+    /// Print a saved decision tree as if/else rules
+    Rules(RulesArgs),
+    /// Combine the predictions of several saved models into one submission file
+    Ensemble(EnsembleArgs),
+    /// Report the confusion matrix, precision, recall, F1, MCC, log-loss, Brier, ECE, ROC-AUC
+    /// and PR-AUC of a saved model on a labeled csv
+    Evaluate(EvaluateArgs),
+    /// Tune the decision threshold on validation data and save it with the model
+    TuneThreshold(TuneThresholdArgs),
+    /// Fit a probability calibration on validation data and save it with the model
+    Calibrate(CalibrateArgs),
+    /// Print the coefficients of a logistic regression model by feature
+    Coefficients(CoefficientsArgs),
+    /// Render the exploratory charts for a labeled csv
+    Explore(ExploreArgs),
+    /// Write a reproducible stratified train/validation split of labeled csv files
+    Split(SplitArgs),
+    /// Cross-validate a model configuration with repeated stratified k-fold
+    Cv(CvArgs),
+    /// Chart the training loss against an exponentially growing learning rate
+    LrFind(LrFindArgs),
+    /// Search model and training settings by cross-validation
+    Search(SearchArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TrainArgs {
+    /// Labeled csv files used for training, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "data/train.csv")]
+    pub train: Vec<String>,
+
+    /// Labeled csv used for validation after every epoch, ignored with --valid-ratio
+    #[arg(long, default_value = "data/validation.csv")]
+    pub valid: String,
+
+    /// Where to save the model bundle, without the `.mpk`/`.json` extension
+    #[arg(long, default_value = "model/bce-adam")]
+    pub model: String,
+
+    /// Continue from the last checkpoint in `<model>.checkpoints` with its optimizer state
+    #[arg(long)]
+    pub resume: bool,
+
+    #[command(flatten)]
+    pub exp: ExpArgs,
+
+    #[command(flatten)]
+    pub model_config: ModelArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TrainGbdtArgs {
+    /// Labeled csv files used for training, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "data/train.csv")]
+    pub train: Vec<String>,
+
+    /// Labeled csv used for early stopping and validation, ignored with --valid-ratio
+    #[arg(long, default_value = "data/validation.csv")]
+    pub valid: String,
+
+    /// Where to save the model bundle, without the `.json` extension
+    #[arg(long, default_value = "model/gbdt")]
+    pub model: String,
+
+    /// Gradient-boosted trees config json, e.g. the `config` entry of a saved bundle
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Maximum number of boosting rounds
+    #[arg(long)]
+    pub trees: Option<usize>,
+
+    /// Shrinkage of every tree's leaf values
+    #[arg(long)]
+    pub learning_rate: Option<f64>,
+
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    #[arg(long)]
+    pub max_leaves: Option<usize>,
+
+    #[arg(long)]
+    pub min_samples_leaf: Option<usize>,
+
+    /// L2 penalty on the leaf values
+    #[arg(long)]
+    pub l2: Option<f64>,
+
+    /// Histogram bins per feature, at most 256
+    #[arg(long)]
+    pub bins: Option<usize>,
+
+    /// Fraction of the rows each tree is fitted on
+    #[arg(long)]
+    pub subsample: Option<f64>,
+
+    /// Fraction of the features each tree may split on
+    #[arg(long)]
+    pub colsample: Option<f64>,
+
+    /// Rounds without validation improvement before stopping, 0 to grow every tree
+    #[arg(long)]
+    pub early_stopping_rounds: Option<usize>,
+
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub data: TreeDataArgs,
+}
+
+impl TrainGbdtArgs {
+    pub fn config(&self) -> GbdtConfig {
+        let mut config = match &self.config {
+            Some(path) => GbdtConfig::load(path).expect("could not load gbdt config"),
+            None => GbdtConfig::new(),
+        };
+        if let Some(trees) = self.trees {
+            config.trees = trees;
+        }
+        if let Some(learning_rate) = self.learning_rate {
+            config.learning_rate = learning_rate;
+        }
+        if let Some(max_depth) = self.max_depth {
+            config.max_depth = max_depth;
+        }
+        if let Some(max_leaves) = self.max_leaves {
+            config.max_leaves = max_leaves;
+        }
+        if let Some(min_samples_leaf) = self.min_samples_leaf {
+            config.min_samples_leaf = min_samples_leaf;
+        }
+        if let Some(l2) = self.l2 {
+            config.l2 = l2;
+        }
+        if let Some(bins) = self.bins {
+            config.bins = bins;
+        }
+        if let Some(subsample) = self.subsample {
+            config.subsample = subsample;
+        }
+        if let Some(colsample) = self.colsample {
+            config.colsample = colsample;
+        }
+        if let Some(rounds) = self.early_stopping_rounds {
+            config.early_stopping_rounds = rounds;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        self.data.apply(&mut config.split, &mut config.features);
+        config
+    }
+}
+
+/// The held out split and feature flags shared by `train-gbdt`, `train-tree` and
+/// `train-forest`.
+#[derive(Args, Debug)]
+pub struct TreeDataArgs {
+    /// Hold out this fraction of the training data for validation instead of reading --valid
+    #[arg(long, value_parser = ratio)]
+    pub valid_ratio: Option<f64>,
+
+    /// Comma separated derived features to switch off
+    #[arg(long, value_delimiter = ',')]
+    pub disable_features: Vec<String>,
+}
+
+impl TreeDataArgs {
+    pub fn apply(&self, split: &mut Option<SplitConfig>, features: &mut FeatureConfig) {
+        if let Some(valid_ratio) = self.valid_ratio {
+            split.get_or_insert_with(SplitConfig::new).valid_ratio = valid_ratio;
+        }
+        disable_features(&self.disable_features, features);
+    }
+}
+
+/// CART settings shared by `train-tree` and `train-forest`.
+#[derive(Args, Debug)]
+pub struct TreeArgs {
+    /// Split criterion: gini or entropy
+    #[arg(long)]
+    pub criterion: Option<Criterion>,
+
+    /// Deepest split level, 3 for train-tree and 8 for train-forest by default
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Nodes with fewer rows are not split
+    #[arg(long)]
+    pub min_samples_split: Option<usize>,
+
+    #[arg(long)]
+    pub min_samples_leaf: Option<usize>,
+
+    /// Features considered at every split: all, sqrt, log2 or a number
+    #[arg(long)]
+    pub max_features: Option<MaxFeatures>,
+}
+
+impl TreeArgs {
+    pub fn apply(&self, config: &mut TreeConfig) {
+        if let Some(criterion) = &self.criterion {
+            config.criterion = criterion.clone();
+        }
+        if let Some(max_depth) = self.max_depth {
+            config.max_depth = max_depth;
+        }
+        if let Some(min_samples_split) = self.min_samples_split {
+            config.min_samples_split = min_samples_split;
+        }
+        if let Some(min_samples_leaf) = self.min_samples_leaf {
+            config.min_samples_leaf = min_samples_leaf;
+        }
+        if let Some(max_features) = &self.max_features {
+            config.max_features = max_features.clone();
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct TrainTreeArgs {
+    /// Labeled csv files used for training, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "data/train.csv")]
+    pub train: Vec<String>,
+
+    /// Labeled csv used for validation, ignored with --valid-ratio
+    #[arg(long, default_value = "data/validation.csv")]
+    pub valid: String,
+
+    /// Where to save the model bundle, without the `.json` extension
+    #[arg(long, default_value = "model/tree")]
+    pub model: String,
+
+    #[command(flatten)]
+    pub data: TreeDataArgs,
+
+    #[command(flatten)]
+    pub tree: TreeArgs,
+}
+
+impl TrainTreeArgs {
+    pub fn config(&self) -> ForestConfig {
+        let mut config = ForestConfig::single_tree();
+        self.tree.apply(&mut config.tree);
+        self.data.apply(&mut config.split, &mut config.features);
+        config
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct TrainForestArgs {
+    /// Labeled csv files used for training, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "data/train.csv")]
+    pub train: Vec<String>,
+
+    /// Labeled csv used for validation, ignored with --valid-ratio
+    #[arg(long, default_value = "data/validation.csv")]
+    pub valid: String,
+
+    /// Where to save the model bundle, without the `.json` extension
+    #[arg(long, default_value = "model/forest")]
+    pub model: String,
+
+    /// Random forest config json, e.g. the `config` entry of a saved bundle
+    #[arg(long)]
+    pub config: Option<String>,
+
+    #[arg(long)]
+    pub trees: Option<usize>,
+
+    /// Fit every tree on a bootstrap sample of the rows, needed for the out-of-bag accuracy
+    #[arg(long)]
+    pub bootstrap: Option<bool>,
+
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub data: TreeDataArgs,
+
+    #[command(flatten)]
+    pub tree: TreeArgs,
+}
+
+impl TrainForestArgs {
+    pub fn config(&self) -> ForestConfig {
+        let mut config = match &self.config {
+            Some(path) => ForestConfig::load(path).expect("could not load forest config"),
+            None => ForestConfig::new(),
+        };
+        if let Some(trees) = self.trees {
+            config.trees = trees;
+        }
+        if let Some(bootstrap) = self.bootstrap {
+            config.bootstrap = bootstrap;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        self.tree.apply(&mut config.tree);
+        self.data.apply(&mut config.split, &mut config.features);
+        config
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RulesArgs {
+    /// Decision tree bundle, without the `.json` extension. Forests print their first tree
+    #[arg(long, default_value = "model/tree")]
+    pub model: String,
+}
+
+#[derive(Args, Debug)]
+pub struct InferArgs {
+    /// Unlabeled csv to predict
+    #[arg(long, default_value = "data/test.csv")]
+    pub input: String,
+
+    /// Where to write the `PassengerId,Survived` submission
+    #[arg(long, default_value = "data/submission.csv")]
+    pub output: String,
+
+    /// Saved model bundle of any kind, without the `.mpk`/`.json` extension
+    #[arg(long, default_value = "model/bce-adam")]
+    pub model: String,
+
+    /// Survival probability at or above which a passenger is predicted to survive, the
+    /// model's tuned threshold or 0.5 by default
+    #[arg(long)]
+    pub threshold: Option<f32>,
+
+    /// Also write the survival probability as a third column
+    #[arg(long)]
+    pub probabilities: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct EnsembleArgs {
+    /// Saved model bundles of any kind, comma separated, without the `.mpk`/`.json` extension
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub models: Vec<String>,
+
+    /// How to combine the survival probabilities: mean, weighted or stacked
+    #[arg(long, default_value = "mean")]
+    pub combiner: Combiner,
+
+    /// Comma separated weight of every model for --combiner weighted, their validation
+    /// accuracy by default
+    #[arg(long, value_delimiter = ',')]
+    pub weights: Vec<f64>,
+
+    /// Labeled csv files the models are refitted on, fold by fold, to train the stacked
+    /// meta-learner on out-of-fold predictions
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values = ["data/train.csv", "data/validation.csv"]
+    )]
+    pub train: Vec<String>,
+
+    /// Number of folds for --combiner stacked
+    #[arg(long, default_value_t = 5)]
+    pub folds: usize,
+
+    /// L2 penalty on the stacked meta-learner's coefficients
+    #[arg(long)]
+    pub meta_l2: Option<f64>,
+
+    /// Labeled csv to report every model and the ensemble on
+    #[arg(long)]
+    pub valid: Option<String>,
+
+    /// Unlabeled csv to predict
+    #[arg(long, default_value = "data/test.csv")]
+    pub input: String,
+
+    /// Where to write the `PassengerId,Survived` submission
+    #[arg(long, default_value = "data/submission.csv")]
+    pub output: String,
+
+    /// Survival probability at or above which a passenger is predicted to survive, the mean
+    /// of the models' tuned thresholds or 0.5 by default
+    #[arg(long)]
+    pub threshold: Option<f32>,
+
+    /// Also write the survival probability as a third column
+    #[arg(long)]
+    pub probabilities: bool,
+}
+
+impl EnsembleArgs {
+    pub fn config(&self) -> EnsembleConfig {
+        let mut config = EnsembleConfig::new()
+            .with_combiner(self.combiner.clone())
+            .with_cv(CvConfig::new().with_folds(self.folds));
+        if !self.weights.is_empty() {
+            config.weights = Some(self.weights.clone());
+        }
+        if let Some(meta_l2) = self.meta_l2 {
+            config.meta_l2 = meta_l2;
+        }
+        config
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct EvaluateArgs {
+    /// Labeled csv to evaluate against
+    #[arg(long, default_value = "data/validation.csv")]
+    pub input: String,
+
+    /// Saved model bundle of any kind, without the `.mpk`/`.json` extension
+    #[arg(long, default_value = "model/bce-adam")]
+    pub model: String,
+
+    /// Survival probability at or above which a passenger is predicted to survive, the
+    /// model's tuned threshold or 0.5 by default
+    #[arg(long)]
+    pub threshold: Option<f32>,
+
+    /// Also write the metrics report to this `.json` or `.csv` file
+    #[arg(long)]
+    pub report: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct CvArgs {
+    /// Labeled csv files to cross-validate on, comma separated
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values = ["data/train.csv", "data/validation.csv"]
+    )]
+    pub train: Vec<String>,
+
+    /// Number of folds
+    #[arg(long, default_value_t = 5)]
+    pub folds: usize,
+
+    /// Number of times the folds are reshuffled, with seeds seed, seed + 1, ...
+    #[arg(long, default_value_t = 1)]
+    pub repeats: usize,
+
+    /// Write the out-of-fold probabilities of every passenger to this csv
+    #[arg(long)]
+    pub oof: Option<String>,
+
+    /// Model to cross-validate: nn, gbdt or forest
+    #[arg(long, default_value = "nn")]
+    pub kind: ModelKind,
+
+    /// Gradient-boosted trees config json for --kind gbdt, e.g. the `config` entry of a saved
+    /// bundle
+    #[arg(long)]
+    pub gbdt_config: Option<String>,
+
+    /// Random forest config json for --kind forest, e.g. the `config` entry of a saved bundle
+    #[arg(long)]
+    pub forest_config: Option<String>,
+
+    /// --stratify also applies to the folds. Only the seed, imputation, scaling, split and
+    /// feature flags apply to gbdt and forest
+    #[command(flatten)]
+    pub exp: ExpArgs,
+
+    #[command(flatten)]
+    pub model_config: ModelArgs,
+}
+
+impl CvArgs {
+    pub fn config(&self) -> CvConfig {
+        CvConfig::new()
+            .with_folds(self.folds)
+            .with_repeats(self.repeats)
+    }
+
+    /// Panics on flags that don't configure the chosen kind of model.
+    pub fn classifier(&self) -> ClassifierConfig {
+        let mut ignored: Vec<&str> = Vec::new();
+        if self.kind != ModelKind::Gbdt && self.gbdt_config.is_some() {
+            ignored.push("--gbdt-config");
+        }
+        if self.kind != ModelKind::Forest && self.forest_config.is_some() {
+            ignored.push("--forest-config");
+        }
+        if self.kind != ModelKind::NeuralNet {
+            ignored.extend(self.exp.network_flags());
+            ignored.extend(self.model_config.network_flags());
+        }
+        if !ignored.is_empty() {
+            panic!(
+                "{} cannot be used to cross-validate {}",
+                ignored.join(", "),
+                self.kind.name().to_lowercase()
+            );
+        }
+
+        match self.kind {
+            ModelKind::NeuralNet => ClassifierConfig::NeuralNet {
+                experiment: self.exp.config(),
+                model: self.model_config.config(),
+            },
+            ModelKind::Gbdt => {
+                let mut config = match &self.gbdt_config {
+                    Some(path) => GbdtConfig::load(path).expect("could not load gbdt config"),
+                    None => GbdtConfig::new(),
+                };
+                self.exp.apply_data(
+                    &mut config.seed,
+                    &mut config.impute,
+                    &mut config.preprocess,
+                    &mut config.split,
+                );
+                self.model_config.apply_features(&mut config.features);
+                ClassifierConfig::Gbdt(config)
+            }
+            ModelKind::Forest => {
+                let mut config = match &self.forest_config {
+                    Some(path) => ForestConfig::load(path).expect("could not load forest config"),
+                    None => ForestConfig::new(),
+                };
+                self.exp.apply_data(
+                    &mut config.seed,
+                    &mut config.impute,
+                    &mut config.preprocess,
+                    &mut config.split,
+                );
+                self.model_config.apply_features(&mut config.features);
+                ClassifierConfig::Forest(config)
+            }
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct LrFindArgs {
+    /// Labeled csv files to train on, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "data/train.csv")]
+    pub train: Vec<String>,
+
+    /// Where to write the loss vs. learning rate chart
+    #[arg(long, default_value = "lr_find.html")]
+    pub output: String,
+
+    /// Learning rate of the first step
+    #[arg(long, default_value_t = 1e-7)]
+    pub start_lr: f64,
+
+    /// Learning rate of the last step
+    #[arg(long, default_value_t = 1.0)]
+    pub end_lr: f64,
+
+    /// Number of optimizer steps, cycling through the training data as needed
+    #[arg(long, default_value_t = 200)]
+    pub steps: usize,
+
+    #[command(flatten)]
+    pub exp: ExpArgs,
+
+    #[command(flatten)]
+    pub model_config: ModelArgs,
+}
+
+impl LrFindArgs {
+    pub fn config(&self) -> LrFindConfig {
+        LrFindConfig::new()
+            .with_start_lr(self.start_lr)
+            .with_end_lr(self.end_lr)
+            .with_steps(self.steps)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// Labeled csv files to cross-validate on, comma separated
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values = ["data/train.csv", "data/validation.csv"]
+    )]
+    pub train: Vec<String>,
+
+    /// Search space json, see search_space.json
+    #[arg(long, default_value = "search_space.json")]
+    pub space: String,
+
+    /// Search strategy: grid, random, halving or hyperband
+    #[arg(long, default_value = "random")]
+    pub strategy: Strategy,
+
+    /// Configurations sampled by random search and successive halving
+    #[arg(long, default_value_t = 20)]
+    pub trials: usize,
+
+    /// Values per range parameter in grid search
+    #[arg(long, default_value_t = 3)]
+    pub grid_points: usize,
+
+    /// Budget multiplier between successive halving rungs
+    #[arg(long, default_value_t = 3)]
+    pub eta: usize,
+
+    /// Smallest epoch budget of successive halving and hyperband, --epochs being the largest
+    #[arg(long, default_value_t = 1, value_parser = positive)]
+    pub min_epochs: usize,
+
+    /// Number of cross-validation folds per trial
+    #[arg(long, default_value_t = 3)]
+    pub folds: usize,
+
+    /// Where to write the table of every evaluated trial
+    #[arg(long, default_value = "search.csv")]
+    pub results: String,
+
+    /// Best configuration is saved to `<output>.exp.json` and `<output>.model.json`
+    #[arg(long, default_value = "model/search")]
+    pub output: String,
+
+    /// Base configuration the searched parameters are applied to
+    #[command(flatten)]
+    pub exp: ExpArgs,
+
+    #[command(flatten)]
+    pub model_config: ModelArgs,
+}
+
+impl SearchArgs {
+    pub fn config(&self) -> SearchConfig {
+        SearchConfig::new()
+            .with_strategy(self.strategy.clone())
+            .with_trials(self.trials)
+            .with_grid_points(self.grid_points)
+            .with_eta(self.eta)
+            .with_min_epochs(self.min_epochs)
+            .with_cv(CvConfig::new().with_folds(self.folds))
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct TuneThresholdArgs {
+    /// Saved model bundle of any kind, without the `.mpk`/`.json` extension
+    #[arg(long, default_value = "model/bce-adam")]
+    pub model: String,
+
+    /// Metric to maximize: accuracy, f1 or youden, or cost[:fp[:fn]] to minimize the cost of
+    /// false positives and false negatives
+    #[arg(long, default_value = "accuracy")]
+    pub objective: Objective,
+
+    /// Labeled csv to tune on, the model's own validation data by default
+    #[arg(long)]
+    pub valid: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct CalibrateArgs {
+    /// Saved model bundle of any kind, without the `.mpk`/`.json` extension
+    #[arg(long, default_value = "model/bce-adam")]
+    pub model: String,
+
+    /// platt, temperature or isotonic
+    #[arg(long, default_value = "platt")]
+    pub method: CalibrationMethod,
+
+    /// Labeled csv to fit on, the model's own validation data by default
+    #[arg(long)]
+    pub valid: Option<String>,
+
+    /// Number of equal-width probability bins of the reliability diagram and ECE
+    #[arg(long, default_value_t = CALIBRATION_BINS, value_parser = positive)]
+    pub bins: usize,
+
+    /// Where to write the reliability diagram
+    #[arg(long, default_value = "reliability.html")]
+    pub output: String,
+}
+
+#[derive(Args, Debug)]
+pub struct CoefficientsArgs {
+    /// Model bundle trained with --hidden-layers none, without the `.mpk`/`.json` extension
+    #[arg(long, default_value = "model/bce-adam")]
+    pub model: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ExploreArgs {
+    /// Labeled csv to chart
+    #[arg(long, default_value = "data/train.csv")]
+    pub input: String,
+
+    /// Directory the html charts are written to
+    #[arg(long, default_value = ".")]
+    pub output_dir: String,
+}
+
+#[derive(Args, Debug)]
+pub struct SplitArgs {
+    /// Labeled csv files to split, comma separated
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values = ["data/train.csv", "data/validation.csv"]
+    )]
+    pub input: Vec<String>,
+
+    /// Where to write the training partition
+    #[arg(long, default_value = "data/split/train.csv")]
+    pub train_output: String,
+
+    /// Where to write the validation partition
+    #[arg(long, default_value = "data/split/validation.csv")]
+    pub valid_output: String,
+
+    /// Share of passengers held out for validation
+    #[arg(long, default_value_t = 0.1, value_parser = ratio)]
+    pub valid_ratio: f64,
+
+    /// Seed for the shuffle, the same seed always gives the same split
+    #[arg(long, default_value_t = 42)]
+    pub seed: u64,
+
+    /// Columns to stratify by on top of survived, comma separated: pclass, sex
+    #[arg(long, value_delimiter = ',')]
+    pub stratify: Vec<String>,
+}
+
+impl SplitArgs {
+    pub fn config(&self) -> SplitConfig {
+        let mut config = SplitConfig::new().with_valid_ratio(self.valid_ratio);
+        for name in &self.stratify {
+            config
+                .stratify_by(name)
+                .unwrap_or_else(|err| panic!("{}", err));
+        }
+        config
+    }
+}
+
+/// A count that must be at least 1.
+fn positive(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// A share strictly between 0 and 1.
+fn ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value > 0.0 && value < 1.0 => Ok(value),
+        Ok(_) => Err("must be between 0 and 1".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Switches off the named derived features, panicking on an unknown name.
+fn disable_features(names: &[String], features: &mut FeatureConfig) {
+    for feature in names {
+        features
+            .set(feature, false)
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+}
+
+/// Overrides applied on top of the default or loaded `ExpConfig`.
+#[derive(Args, Debug)]
+pub struct ExpArgs {
+    /// Experiment config json, e.g. the `.exp.json` saved next to a trained model
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Number of training epochs
+    #[arg(long, value_parser = positive)]
+    pub epochs: Option<usize>,
+
+    /// Number of data loading workers
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// Seed for weight initialization and shuffling
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Maximum number of training rows
+    #[arg(long)]
+    pub dataset_size: Option<usize>,
+
+    /// Optimizer learning rate
+    #[arg(long)]
+    pub learning_rate: Option<f64>,
+
+    /// Mini-batch size, use the dataset size for full-batch training
+    #[arg(long, value_parser = positive)]
+    pub batch_size: Option<usize>,
+
+    /// Optimizer: adam, adamw, sgd or rmsprop
+    #[arg(long)]
+    pub optimizer: Option<OptimizerKind>,
+
+    /// L2 penalty, decoupled for adamw
+    #[arg(long)]
+    pub weight_decay: Option<f64>,
+
+    /// Momentum of sgd and rmsprop
+    #[arg(long)]
+    pub momentum: Option<f64>,
+
+    /// Clip the gradient norm of every parameter to this value
+    #[arg(long)]
+    pub grad_clip: Option<f32>,
+
+    /// L1 penalty on the linear layer weights, sparsifies a logistic regression
+    #[arg(long)]
+    pub l1: Option<f64>,
+
+    /// L2 penalty on the linear layer weights
+    #[arg(long)]
+    pub l2: Option<f64>,
+
+    /// Learning rate schedule: constant, step[:every[:gamma]], cosine[:min_lr],
+    /// one-cycle[:max_lr[:pct_start]] or plateau[:factor[:patience[:min_lr]]]
+    #[arg(long)]
+    pub schedule: Option<Schedule>,
+
+    /// Epochs of linear learning rate warmup
+    #[arg(long)]
+    pub warmup_epochs: Option<usize>,
+
+    /// Feature scaling fitted on the training csv: none, standard, min-max or robust
+    #[arg(long)]
+    pub scaler: Option<Scaler>,
+
+    /// Apply log(1 + fare) before scaling
+    #[arg(long)]
+    pub log_fare: Option<bool>,
+
+    /// How missing ages are filled: mean, median, mode, group-median, constant:<value> or knn:<k>
+    #[arg(long)]
+    pub impute_age: Option<ImputeStrategy>,
+
+    /// How missing fares are filled, same choices as --impute-age
+    #[arg(long)]
+    pub impute_fare: Option<ImputeStrategy>,
+
+    /// Add a 0/1 column for each imputed field
+    #[arg(long)]
+    pub missing_indicators: Option<bool>,
+
+    /// Hold out this share of the training csv for validation instead of reading --valid
+    #[arg(long, value_parser = ratio)]
+    pub valid_ratio: Option<f64>,
+
+    /// Columns the held out split is stratified by on top of survived, comma separated: pclass, sex
+    #[arg(long, value_delimiter = ',')]
+    pub stratify: Vec<String>,
+
+    /// Stop after this many epochs without improvement of the monitored value
+    #[arg(long, value_parser = positive)]
+    pub patience: Option<usize>,
+
+    /// Value early stopping watches: valid-loss, valid-accuracy, valid-f1 or valid-roc-auc
+    #[arg(long)]
+    pub monitor: Option<Monitor>,
+
+    /// Smallest change of the monitored value that counts as an improvement
+    #[arg(long)]
+    pub min_delta: Option<f64>,
+
+    /// Keep the weights of the best epoch instead of the last one when early stopping
+    #[arg(long)]
+    pub restore_best: Option<bool>,
+}
+
+impl ExpArgs {
+    pub fn config(&self) -> ExpConfig {
+        let config = match &self.config {
+            Some(path) => ExpConfig::load(path).expect("could not load experiment config"),
+            None => ExpConfig::new(),
+        };
+        self.apply(config)
+    }
+
+    pub fn apply(&self, mut config: ExpConfig) -> ExpConfig {
+        if let Some(epochs) = self.epochs {
+            config.epochs = epochs;
+        }
+        if let Some(workers) = self.workers {
+            config.workers = workers;
+        }
+        if let Some(dataset_size) = self.dataset_size {
+            config.dataset_size = Some(dataset_size);
+        }
+        if let Some(learning_rate) = self.learning_rate {
+            config.learning_rate = learning_rate;
+        }
+        if let Some(batch_size) = self.batch_size {
+            config.batch_size = batch_size;
+        }
+        if let Some(kind) = &self.optimizer {
+            config.optimizer.kind = kind.clone();
+        }
+        if let Some(weight_decay) = self.weight_decay {
+            config.optimizer.weight_decay = weight_decay;
+        }
+        if let Some(momentum) = self.momentum {
+            config.optimizer.momentum = momentum;
+        }
+        if let Some(grad_clip) = self.grad_clip {
+            config.optimizer.grad_clip_norm = Some(grad_clip);
+        }
+        if let Some(l1) = self.l1 {
+            config.l1_penalty = l1;
+        }
+        if let Some(l2) = self.l2 {
+            config.l2_penalty = l2;
+        }
+        if let Some(schedule) = &self.schedule {
+            config.scheduler.schedule = schedule.clone();
+        }
+        if let Some(warmup_epochs) = self.warmup_epochs {
+            config.scheduler.warmup_epochs = warmup_epochs;
+        }
+        self.apply_data(
+            &mut config.seed,
+            &mut config.impute,
+            &mut config.preprocess,
+            &mut config.split,
+        );
+        if self.patience.is_some()
+            || self.monitor.is_some()
+            || self.min_delta.is_some()
+            || self.restore_best.is_some()
+        {
+            let early_stopping = config
+                .early_stopping
+                .get_or_insert_with(EarlyStoppingConfig::new);
+            if let Some(patience) = self.patience {
+                early_stopping.patience = patience;
+            }
+            if let Some(monitor) = &self.monitor {
+                early_stopping.monitor = monitor.clone();
+            }
+            if let Some(min_delta) = self.min_delta {
+                early_stopping.min_delta = min_delta;
+            }
+            if let Some(restore_best) = self.restore_best {
+                early_stopping.restore_best = restore_best;
+            }
+        }
+        config
+    }
+
+    /// The settings every kind of model shares: the seed, imputation, scaling and the held out
+    /// split.
+    pub fn apply_data(
+        &self,
+        seed: &mut u64,
+        impute: &mut ImputeConfig,
+        preprocess: &mut PreprocessConfig,
+        split: &mut Option<SplitConfig>,
+    ) {
+        if let Some(value) = self.seed {
+            *seed = value;
+        }
+        if let Some(scaler) = &self.scaler {
+            preprocess.scaler = scaler.clone();
+        }
+        if let Some(log_fare) = self.log_fare {
+            preprocess.log_fare = log_fare;
+        }
+        if let Some(strategy) = &self.impute_age {
+            impute.age = strategy.clone();
+        }
+        if let Some(strategy) = &self.impute_fare {
+            impute.fare = strategy.clone();
+        }
+        if let Some(missing_indicators) = self.missing_indicators {
+            impute.missing_indicators = missing_indicators;
+        }
+        if let Some(valid_ratio) = self.valid_ratio {
+            split.get_or_insert_with(SplitConfig::new).valid_ratio = valid_ratio;
+        }
+        if !self.stratify.is_empty() {
+            let split = split.get_or_insert_with(SplitConfig::new);
+            for name in &self.stratify {
+                split
+                    .stratify_by(name)
+                    .unwrap_or_else(|err| panic!("{}", err));
+            }
+        }
+    }
+
+    /// Flags given on the command line that only configure the neural network.
+    pub fn network_flags(&self) -> Vec<&'static str> {
+        let flags = [
+            ("--config", self.config.is_some()),
+            ("--epochs", self.epochs.is_some()),
+            ("--workers", self.workers.is_some()),
+            ("--dataset-size", self.dataset_size.is_some()),
+            ("--learning-rate", self.learning_rate.is_some()),
+            ("--batch-size", self.batch_size.is_some()),
+            ("--optimizer", self.optimizer.is_some()),
+            ("--weight-decay", self.weight_decay.is_some()),
+            ("--momentum", self.momentum.is_some()),
+            ("--grad-clip", self.grad_clip.is_some()),
+            ("--l1", self.l1.is_some()),
+            ("--l2", self.l2.is_some()),
+            ("--schedule", self.schedule.is_some()),
+            ("--warmup-epochs", self.warmup_epochs.is_some()),
+            ("--patience", self.patience.is_some()),
+            ("--monitor", self.monitor.is_some()),
+            ("--min-delta", self.min_delta.is_some()),
+            ("--restore-best", self.restore_best.is_some()),
+        ];
+        flags
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(flag, _)| flag)
+            .collect()
+    }
+}
+
+/// Overrides applied on top of the default or loaded `ModelConfig`.
+#[derive(Args, Debug)]
+pub struct ModelArgs {
+    /// Model config json describing the hidden layers, e.g. the `model` entry of a saved bundle
+    #[arg(long)]
+    pub model_config: Option<String>,
+
+    /// Comma separated widths of the hidden layers, e.g. `128,64`, or `none` for no hidden layer
+    #[arg(long, value_delimiter = ',')]
+    pub hidden_layers: Option<Vec<String>>,
+
+    /// Activation of every hidden layer: relu, gelu, leaky-relu or silu
+    #[arg(long)]
+    pub activation: Option<Activation>,
+
+    /// Dropout rate after every hidden layer
+    #[arg(long)]
+    pub dropout: Option<f64>,
+
+    /// Normalization of every hidden layer: none, batch or layer
+    #[arg(long)]
+    pub norm: Option<Norm>,
+
+    /// Add residual connections around every hidden layer
+    #[arg(long)]
+    pub residual: Option<bool>,
+
+    /// Comma separated derived features to leave out, e.g. `deck,ticket_prefix`
+    #[arg(long, value_delimiter = ',')]
+    pub disable_features: Vec<String>,
+
+    /// Comma separated categoricals to embed instead of one-hot, as `column` or `column:dim`,
+    /// e.g. `pclass:2,title:3`. Columns: pclass, sex, embarked, title, deck
+    #[arg(long, value_delimiter = ',')]
+    pub embed: Option<Vec<EmbeddingConfig>>,
+}
+
+impl ModelArgs {
+    pub fn config(&self) -> ModelConfig {
+        let config = match &self.model_config {
+            Some(path) => ModelConfig::load(path).expect("could not load model config"),
+            None => ModelConfig::new(),
+        };
+        self.apply(config)
+    }
+
+    pub fn apply(&self, mut config: ModelConfig) -> ModelConfig {
+        if let Some(widths) = &self.hidden_layers {
+            config.hidden_layers = widths
+                .iter()
+                .filter(|width| width.as_str() != "none")
+                .map(|width| {
+                    let width = width
+                        .parse()
+                        .unwrap_or_else(|_| panic!("invalid hidden layer width {}", width));
+                    LayerConfig::new(width)
+                })
+                .collect();
+        }
+        for layer in config.hidden_layers.iter_mut() {
+            if let Some(activation) = &self.activation {
+                layer.activation = activation.clone();
+            }
+            if let Some(dropout) = self.dropout {
+                layer.dropout = dropout;
+            }
+            if let Some(norm) = &self.norm {
+                layer.norm = norm.clone();
+            }
+            if let Some(residual) = self.residual {
+                layer.residual = residual;
+            }
+        }
+        if let Some(embeddings) = &self.embed {
+            config.embeddings = embeddings.clone();
+        }
+        self.apply_features(&mut config.features);
+        config
+    }
+
+    pub fn apply_features(&self, features: &mut FeatureConfig) {
+        disable_features(&self.disable_features, features);
+    }
+
+    /// Flags given on the command line that only configure the neural network.
+    pub fn network_flags(&self) -> Vec<&'static str> {
+        let flags = [
+            ("--model-config", self.model_config.is_some()),
+            ("--hidden-layers", self.hidden_layers.is_some()),
+            ("--activation", self.activation.is_some()),
+            ("--dropout", self.dropout.is_some()),
+            ("--norm", self.norm.is_some()),
+            ("--residual", self.residual.is_some()),
+            ("--embed", self.embed.is_some()),
+        ];
+        flags
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(flag, _)| flag)
+            .collect()
+    }
+}
@@ -0,0 +1,224 @@
+use burn::{config::Config, tensor::backend::AutodiffBackend};
+
+use crate::{
+    classifier::{ClassifierConfig, TrainingData},
+    data::{RawData, SplitConfig, TitanicRecord},
+    metrics::Metrics,
+    model::DEFAULT_THRESHOLD,
+};
+
+/// Repeated stratified k-fold cross-validation. Every repeat reshuffles the strata with
+/// `seed + repeat`, so each passenger is held out exactly once per repeat.
+#[derive(Config, Debug)]
+pub struct CvConfig {
+    #[config(default = 5)]
+    pub folds: usize,
+
+    #[config(default = 1)]
+    pub repeats: usize,
+}
+
+/// Out-of-fold survival probability of one passenger.
+#[derive(Clone, Debug)]
+pub struct OofPrediction {
+    pub id: u32,
+    pub repeat: usize,
+    pub fold: usize,
+    pub survived: u8,
+    pub probability: f32,
+}
+
+pub struct CvResult {
+    pub losses: Vec<f64>,
+    pub accuracies: Vec<f64>,
+    pub oof: Vec<OofPrediction>,
+}
+
+/// Mean and sample standard deviation.
+pub fn mean_std(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance.sqrt())
+}
+
+/// Deals each shuffled stratum round-robin over the folds, so every fold keeps the survival
+/// rate (and class/sex mix when stratified by them) of the whole set.
+pub fn stratified_folds(
+    records: &[TitanicRecord],
+    split: &SplitConfig,
+    folds: usize,
+    seed: u64,
+) -> Vec<Vec<TitanicRecord>> {
+    let mut assigned = vec![Vec::new(); folds];
+    let mut next = 0;
+    for stratum in split.strata(records, seed) {
+        for record in stratum {
+            assigned[next % folds].push(record);
+            next += 1;
+        }
+    }
+    assigned
+}
+
+pub fn cross_validate<B: AutodiffBackend>(
+    records: &[TitanicRecord],
+    cv: &CvConfig,
+    config: &ClassifierConfig,
+    verbose: bool,
+    device: &B::Device,
+) -> CvResult {
+    assert!(cv.folds >= 2, "cross-validation needs at least 2 folds");
+    let split = config.split().cloned().unwrap_or_else(SplitConfig::new);
+
+    let mut losses = Vec::new();
+    let mut accuracies = Vec::new();
+    let mut oof = Vec::new();
+
+    for repeat in 0..cv.repeats {
+        let folds = stratified_folds(records, &split, cv.folds, config.seed() + repeat as u64);
+
+        for (fold, valid) in folds.iter().enumerate() {
+            let mut train: Vec<TitanicRecord> = folds
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != fold)
+                .flat_map(|(_, records)| records.iter().cloned())
+                .collect();
+            train.sort_by_key(|record| record.passenger_id);
+
+            // Early stopping, best-epoch restore and the plateau schedule watch an inner split
+            // of the training folds, so the held-out fold is only ever scored. Without them the
+            // model fits on every training row.
+            let (train, early_stopping) = if config.watches_validation() {
+                split.split(&train, config.seed() + repeat as u64)
+            } else {
+                (train, Vec::new())
+            };
+            let data = TrainingData {
+                records: train,
+                valid_records: early_stopping,
+                train_path: format!("fold {}.{}", repeat, fold),
+                valid_path: format!("early stopping split of fold {}.{}", repeat, fold),
+            };
+            let model = config.fit::<B>(&data, false, device);
+            let probabilities = model.predict_proba(valid);
+            let labels: Vec<u8> = valid
+                .iter()
+                .map(|record| {
+                    record
+                        .survived
+                        .expect("training data must have a Survived column")
+                })
+                .collect();
+            let metrics = Metrics::new(&probabilities, &labels, DEFAULT_THRESHOLD);
+
+            oof.extend(valid.iter().zip(labels).zip(probabilities).map(
+                |((record, survived), probability)| OofPrediction {
+                    id: record.passenger_id,
+                    repeat,
+                    fold,
+                    survived,
+                    probability,
+                },
+            ));
+
+            if verbose {
+                println!(
+                    "[Fold {}.{}] Train {} | Early stopping {} | Valid {} | Loss {:.3} | Accuracy {:.3} %",
+                    repeat,
+                    fold,
+                    data.records.len(),
+                    data.valid_records.len(),
+                    valid.len(),
+                    metrics.log_loss,
+                    metrics.accuracy * 100.0
+                );
+            }
+            losses.push(metrics.log_loss);
+            accuracies.push(metrics.accuracy * 100.0);
+        }
+    }
+
+    CvResult {
+        losses,
+        accuracies,
+        oof,
+    }
+}
+
+pub fn write_oof(path: &str, oof: &[OofPrediction]) {
+    let mut wtr = csv::Writer::from_path(path).expect("could not create file");
+    wtr.write_record(["PassengerId", "Repeat", "Fold", "Survived", "Probability"])
+        .expect("could not write header");
+    for item in oof {
+        wtr.write_record([
+            &item.id.to_string(),
+            &item.repeat.to_string(),
+            &item.fold.to_string(),
+            &item.survived.to_string(),
+            &format!("{:.6}", item.probability),
+        ])
+        .expect("could not write record");
+    }
+}
+
+/// Cross-validates on labeled csv files and prints per-fold and mean ± std loss and accuracy.
+pub fn run<B: AutodiffBackend>(
+    train_paths: &[String],
+    cv: &CvConfig,
+    config: &ClassifierConfig,
+    oof_path: Option<&str>,
+    device: B::Device,
+) {
+    let raw_data = RawData::from_paths(train_paths);
+    let result = cross_validate::<B>(raw_data.get_all_rows(), cv, config, true, &device);
+
+    let (loss, loss_std) = mean_std(&result.losses);
+    let (accuracy, accuracy_std) = mean_std(&result.accuracies);
+    println!(
+        "*** [CV - {} folds x {}] Loss {:.3} ± {:.3} | Accuracy {:.3} ± {:.3} %",
+        cv.folds, cv.repeats, loss, loss_std, accuracy, accuracy_std
+    );
+
+    let probabilities: Vec<f32> = result.oof.iter().map(|p| p.probability).collect();
+    let labels: Vec<u8> = result.oof.iter().map(|p| p.survived).collect();
+    println!(
+        "*** [CV - out of fold] {}",
+        Metrics::new(&probabilities, &labels, DEFAULT_THRESHOLD).summary()
+    );
+
+    if let Some(path) = oof_path {
+        write_oof(path, &result.oof);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_and_sample_deviation() {
+        assert_eq!(mean_std(&[2.0, 4.0, 6.0]), (4.0, 2.0));
+        assert_eq!(mean_std(&[3.0]), (3.0, 0.0));
+    }
+
+    #[test]
+    fn folds_cover_every_record_once_with_balanced_strata() {
+        let records: Vec<TitanicRecord> = (1..=50)
+            .map(|id| TitanicRecord::example(id, (id <= 20) as u8))
+            .collect();
+        let folds = stratified_folds(&records, &SplitConfig::new(), 5, 42);
+
+        let mut ids: Vec<u32> = folds.iter().flatten().map(|r| r.passenger_id).collect();
+        ids.sort();
+        assert_eq!(ids, (1..=50).collect::<Vec<u32>>());
+        for fold in &folds {
+            assert_eq!(fold.len(), 10);
+            assert_eq!(fold.iter().filter(|r| r.survived == Some(1)).count(), 4);
+        }
+    }
+}
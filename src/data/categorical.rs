@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+use super::{deck, title, TitanicRecord};
+
+/// Passenger attributes that can be fed to the model as a learned embedding instead of a raw
+/// number or one-hot columns.
+#[derive(Config, Debug, PartialEq)]
+pub enum Categorical {
+    Pclass,
+    Sex,
+    Embarked,
+    Title,
+    Deck,
+}
+
+impl FromStr for Categorical {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pclass" => Ok(Categorical::Pclass),
+            "sex" => Ok(Categorical::Sex),
+            "embarked" => Ok(Categorical::Embarked),
+            "title" => Ok(Categorical::Title),
+            "deck" => Ok(Categorical::Deck),
+            _ => Err(format!(
+                "unknown categorical {}, expected pclass, sex, embarked, title or deck",
+                s
+            )),
+        }
+    }
+}
+
+impl Categorical {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Categorical::Pclass => "pclass",
+            Categorical::Sex => "sex",
+            Categorical::Embarked => "embarked",
+            Categorical::Title => "title",
+            Categorical::Deck => "deck",
+        }
+    }
+
+    /// Expects a record already filled in by the `Imputer`.
+    pub fn value(&self, record: &TitanicRecord) -> String {
+        match self {
+            Categorical::Pclass => record.pclass.to_string(),
+            Categorical::Sex => record.sex.clone(),
+            Categorical::Embarked => record
+                .embarked
+                .clone()
+                .expect("embarked should be imputed")
+                .to_ascii_lowercase(),
+            Categorical::Title => title(&record.name).to_string(),
+            Categorical::Deck => deck(record.cabin.as_deref()).to_string(),
+        }
+    }
+}
+
+/// Index 0 of every vocabulary is kept for values never seen in the training split.
+pub const UNKNOWN_CATEGORY: usize = 0;
+
+/// Maps each embedded column to indices, with vocabularies built on the training split and
+/// stored in the model bundle.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CategoryEncoder {
+    pub columns: Vec<Categorical>,
+    pub vocabularies: Vec<Vec<String>>,
+}
+
+impl CategoryEncoder {
+    pub fn fit(columns: &[Categorical], records: &[TitanicRecord]) -> CategoryEncoder {
+        let vocabularies = columns
+            .iter()
+            .map(|column| {
+                let mut values: Vec<String> = records.iter().map(|r| column.value(r)).collect();
+                values.sort();
+                values.dedup();
+                values
+            })
+            .collect();
+
+        CategoryEncoder {
+            columns: columns.to_vec(),
+            vocabularies,
+        }
+    }
+
+    /// Number of embedding rows per column, the unknown index included.
+    pub fn vocab_sizes(&self) -> Vec<usize> {
+        self.vocabularies.iter().map(|v| v.len() + 1).collect()
+    }
+
+    pub fn encode(&self, record: &TitanicRecord) -> Vec<usize> {
+        self.columns
+            .iter()
+            .zip(&self.vocabularies)
+            .map(|(column, vocabulary)| {
+                let value = column.value(record);
+                vocabulary
+                    .iter()
+                    .position(|known| *known == value)
+                    .map(|i| i + 1)
+                    .unwrap_or(UNKNOWN_CATEGORY)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_unseen_values_as_unknown() {
+        let mut woman = TitanicRecord::example(2, 1);
+        woman.sex = "female".to_string();
+        woman.pclass = 1;
+        let records = vec![TitanicRecord::example(1, 0), woman.clone()];
+        let encoder = CategoryEncoder::fit(&[Categorical::Sex, Categorical::Pclass], &records);
+        assert_eq!(encoder.vocab_sizes(), vec![3, 3]);
+        assert_eq!(encoder.encode(&woman), vec![1, 1]);
+
+        let mut second_class = TitanicRecord::example(3, 0);
+        second_class.pclass = 2;
+        assert_eq!(encoder.encode(&second_class), vec![2, UNKNOWN_CATEGORY]);
+    }
+}
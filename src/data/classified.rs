@@ -1,72 +1,106 @@
-#![allow(dead_code)]
-use super::RawData;
-use burn::{prelude::Backend, tensor::Tensor};
+use super::{Pipeline, TitanicRecord};
+use burn::{
+    data::{dataloader::batcher::Batcher, dataset::Dataset},
+    prelude::Backend,
+    tensor::{Int, Tensor, TensorData},
+};
 
+#[derive(Clone, Debug)]
 pub struct Batch<B: Backend> {
     pub inputs: Tensor<B, 2>, // [[f32, f32, ...], [f32, f32, ...], ...]
+    pub categories: Option<Tensor<B, 2, Int>>, // [[usize, ...], ...], none without embeddings
     pub labels: Tensor<B, 2>, // [[1.0], [0.0], [0.0], [1.0],...]
 }
 
-pub struct DataPoint<B: Backend> {
-    pub label: Tensor<B, 1>,   // [0.0] or [1.0]
-    pub feature: Tensor<B, 1>, // [f32, f32, ...]
+#[derive(Clone, Debug)]
+pub struct DataPoint {
+    pub label: f32,             // 0.0 or 1.0
+    pub feature: Vec<f32>,      // [f32, f32, ...]
+    pub categories: Vec<usize>, // [usize, ...]
 }
 
-impl<B: Backend> DataPoint<B> {
-    pub fn new(label: [f32; 1], feature: [f32; 6], device: &B::Device) -> DataPoint<B> {
-        let label: Tensor<B, 1> = Tensor::from_floats(label, device);
-
-        let feature: Tensor<B, 1> = Tensor::from_floats(feature, device);
-        DataPoint { label, feature }
-    }
+pub struct DataSet {
+    pub data: Vec<DataPoint>,
 }
 
-pub struct DataSet<B: Backend> {
-    pub data: Vec<DataPoint<B>>,
+impl DataSet {
+    pub fn from_records(records: &[TitanicRecord], pipeline: &Pipeline) -> DataSet {
+        let mut data = Vec::new();
+        for record in records {
+            let survived = record
+                .survived
+                .expect("training data must have a Survived column");
+
+            let label = survived as f32;
+            let feature = pipeline.transform(record);
+            let categories = pipeline.categories(record);
+
+            data.push(DataPoint {
+                label,
+                feature,
+                categories,
+            });
+        }
+
+        DataSet { data }
+    }
 }
 
-impl<B: Backend> DataSet<B> {
-    pub fn training(device: &B::Device) -> DataSet<B> {
-        let raw_data = RawData::new("data/train.csv");
-        Self::new(raw_data, device)
+impl Dataset<DataPoint> for DataSet {
+    fn get(&self, index: usize) -> Option<DataPoint> {
+        self.data.get(index).cloned()
     }
 
-    pub fn testing(device: &B::Device) -> DataSet<B> {
-        let raw_data = RawData::new("data/validation.csv");
-        Self::new(raw_data, device)
+    fn len(&self) -> usize {
+        self.data.len()
     }
+}
 
-    fn new(raw_data: RawData, device: &B::Device) -> DataSet<B> {
-        let mut data = Vec::new();
-        for record in raw_data.get_all_rows() {
-            let survived = record.survived as f32;
-            let age = record.age as f32;
-            let class = record.pclass as f32;
-            let fare = record.fare;
-            let sex: f32 = if record.sex == "male" { 1.0 } else { 0.0 };
-            let parch = record.parch as f32;
-            let sibsp = record.sib_sp as f32;
+#[derive(Clone)]
+pub struct TitanicBatcher<B: Backend> {
+    device: B::Device,
+}
 
-            let label = [survived];
-            let feature = [age, class, fare, sex, parch, sibsp];
+impl<B: Backend> TitanicBatcher<B> {
+    pub fn new(device: B::Device) -> TitanicBatcher<B> {
+        TitanicBatcher { device }
+    }
 
-            data.push(DataPoint::new(label, feature, device));
-        }
+    pub fn features(&self, features: &[Vec<f32>]) -> Tensor<B, 2> {
+        let rows = features.len();
+        let columns = features.first().map(|f| f.len()).unwrap_or(0);
+        let features: Vec<f32> = features.concat();
+        Tensor::from_data(TensorData::new(features, [rows, columns]), &self.device)
+    }
 
-        DataSet { data }
+    pub fn categories(&self, categories: &[Vec<usize>]) -> Option<Tensor<B, 2, Int>> {
+        let rows = categories.len();
+        let columns = categories.first().map(|c| c.len()).unwrap_or(0);
+        if columns == 0 {
+            return None;
+        }
+        let categories: Vec<i32> = categories.concat().iter().map(|c| *c as i32).collect();
+        Some(Tensor::from_data(
+            TensorData::new(categories, [rows, columns]),
+            &self.device,
+        ))
     }
+}
 
-    pub fn batch(&self) -> Batch<B> {
-        let mut inputs: Vec<Tensor<B, 2>> = Vec::new();
-        let mut labels: Vec<Tensor<B, 2>> = Vec::new();
+impl<B: Backend> Batcher<DataPoint, Batch<B>> for TitanicBatcher<B> {
+    fn batch(&self, items: Vec<DataPoint>) -> Batch<B> {
+        let rows = items.len();
+        let labels: Vec<f32> = items.iter().map(|dp| dp.label).collect();
+        let categories: Vec<Vec<usize>> = items.iter().map(|dp| dp.categories.clone()).collect();
+        let features: Vec<Vec<f32>> = items.into_iter().map(|dp| dp.feature).collect();
 
-        for dp in &self.data {
-            inputs.push(dp.feature.clone().unsqueeze());
-            labels.push(dp.label.clone().unsqueeze());
+        let inputs = self.features(&features);
+        let categories = self.categories(&categories);
+        let labels = Tensor::from_data(TensorData::new(labels, [rows, 1]), &self.device);
+        Batch {
+            inputs,
+            categories,
+            labels,
         }
-
-        let inputs = Tensor::cat(inputs, 0);
-        let labels = Tensor::cat(labels, 0);
-        Batch { inputs, labels }
     }
 }
@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet};
+
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+use super::{Categorical, TitanicRecord};
+
+pub const BASE_FEATURES: [&str; 6] = ["age", "pclass", "fare", "sex", "parch", "sib_sp"];
+pub const TITLES: [&str; 5] = ["mr", "mrs", "miss", "master", "rare"];
+pub const DECKS: [&str; 8] = ["a", "b", "c", "d", "e", "f", "g", "unknown"];
+// The most common ticket prefixes, everything else falls into "other".
+pub const TICKET_PREFIXES: [&str; 9] = [
+    "none", "pc", "ca", "a5", "sotonoq", "stono2", "scparis", "wc", "other",
+];
+pub const PORTS: [&str; 3] = ["c", "q", "s"];
+
+/// Derived features on top of the six base columns, each one can be switched off.
+#[derive(Config, Debug, PartialEq)]
+pub struct FeatureConfig {
+    // One-hot honorific taken from the name: Mr, Mrs, Miss, Master or rare.
+    #[config(default = true)]
+    pub title: bool,
+
+    // One-hot deck letter of the first cabin, "unknown" when there is none.
+    #[config(default = true)]
+    pub deck: bool,
+
+    #[config(default = true)]
+    pub cabin_count: bool,
+
+    #[config(default = true)]
+    pub ticket_prefix: bool,
+
+    // Number of passengers travelling on the same ticket, among the training passengers plus
+    // the one being transformed.
+    #[config(default = true)]
+    pub ticket_group_size: bool,
+
+    // sib_sp + parch + 1
+    #[config(default = true)]
+    pub family_size: bool,
+
+    #[config(default = true)]
+    pub is_alone: bool,
+
+    #[config(default = true)]
+    pub embarked: bool,
+}
+
+impl FeatureConfig {
+    pub const TOGGLES: [&'static str; 8] = [
+        "title",
+        "deck",
+        "cabin_count",
+        "ticket_prefix",
+        "ticket_group_size",
+        "family_size",
+        "is_alone",
+        "embarked",
+    ];
+
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let toggle = match name {
+            "title" => &mut self.title,
+            "deck" => &mut self.deck,
+            "cabin_count" => &mut self.cabin_count,
+            "ticket_prefix" => &mut self.ticket_prefix,
+            "ticket_group_size" => &mut self.ticket_group_size,
+            "family_size" => &mut self.family_size,
+            "is_alone" => &mut self.is_alone,
+            "embarked" => &mut self.embarked,
+            _ => {
+                return Err(format!(
+                    "unknown feature {}, expected one of {:?}",
+                    name,
+                    Self::TOGGLES
+                ))
+            }
+        };
+        *toggle = enabled;
+        Ok(())
+    }
+
+    /// Whether the toggles keep a categorical column at all, pclass and sex always being kept.
+    pub fn keeps(&self, column: &Categorical) -> bool {
+        match column {
+            Categorical::Pclass | Categorical::Sex => true,
+            Categorical::Title => self.title,
+            Categorical::Deck => self.deck,
+            Categorical::Embarked => self.embarked,
+        }
+    }
+}
+
+pub fn title(name: &str) -> &'static str {
+    // "Braund, Mr. Owen Harris"
+    let honorific = name
+        .split_once(',')
+        .and_then(|(_, rest)| rest.split_once('.'))
+        .map(|(title, _)| title.trim())
+        .unwrap_or("");
+
+    match honorific {
+        "Mr" => "mr",
+        "Mrs" | "Mme" => "mrs",
+        "Miss" | "Mlle" | "Ms" => "miss",
+        "Master" => "master",
+        _ => "rare",
+    }
+}
+
+pub fn deck(cabin: Option<&str>) -> &'static str {
+    // "C23 C25 C27" -> "c", the single "T" cabin is treated as unknown
+    match cabin.and_then(|cabin| cabin.chars().next()) {
+        Some('A') => "a",
+        Some('B') => "b",
+        Some('C') => "c",
+        Some('D') => "d",
+        Some('E') => "e",
+        Some('F') => "f",
+        Some('G') => "g",
+        _ => "unknown",
+    }
+}
+
+pub fn cabin_count(cabin: Option<&str>) -> usize {
+    cabin
+        .map(|cabin| cabin.split_whitespace().count())
+        .unwrap_or(0)
+}
+
+pub fn ticket_prefix(ticket: &str) -> &'static str {
+    // "A/5 21171" -> "a5", "STON/O 2. 3101292" -> "stono2", "349909" -> "none"
+    let tokens: Vec<&str> = ticket.split_whitespace().collect();
+    let prefix: String = match tokens.as_slice() {
+        [] => return "none",
+        [only] if only.chars().all(|c| c.is_ascii_digit()) => return "none",
+        [only] => only.to_string(),
+        [prefix @ .., _] => prefix.concat(),
+    }
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric())
+    .collect::<String>()
+    .to_lowercase();
+
+    TICKET_PREFIXES
+        .iter()
+        .find(|known| **known == prefix)
+        .copied()
+        .unwrap_or("other")
+}
+
+fn one_hot(features: &mut Vec<f32>, categories: &[&str], value: &str) {
+    features.extend(
+        categories
+            .iter()
+            .map(|category| if *category == value { 1.0 } else { 0.0 }),
+    );
+}
+
+/// Turns records into model input. Fitted on the training split because the ticket group size
+/// counts training passengers, and stored in the model bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeatureExtractor {
+    pub config: FeatureConfig,
+    pub ticket_counts: HashMap<String, usize>,
+    // Passengers the ticket counts were fitted on, who are already part of their count.
+    #[serde(default)]
+    pub passenger_ids: HashSet<u32>,
+    // Columns fed to the model as embeddings, left out of the numeric features.
+    #[serde(default)]
+    pub embedded: Vec<Categorical>,
+}
+
+impl FeatureExtractor {
+    pub fn fit(
+        config: &FeatureConfig,
+        embedded: &[Categorical],
+        records: &[TitanicRecord],
+    ) -> FeatureExtractor {
+        let mut ticket_counts = HashMap::new();
+        for record in records {
+            *ticket_counts.entry(record.ticket.clone()).or_insert(0) += 1;
+        }
+
+        FeatureExtractor {
+            config: config.clone(),
+            ticket_counts,
+            passenger_ids: records.iter().map(|record| record.passenger_id).collect(),
+            embedded: embedded.to_vec(),
+        }
+    }
+
+    /// Training passengers on the record's ticket, counting the record itself when it was not
+    /// one of them, as for validation and test passengers.
+    pub fn ticket_group_size(&self, record: &TitanicRecord) -> usize {
+        let count = self.ticket_counts.get(&record.ticket).copied().unwrap_or(0);
+        if self.passenger_ids.contains(&record.passenger_id) {
+            count
+        } else {
+            count + 1
+        }
+    }
+
+    fn embeds(&self, column: Categorical) -> bool {
+        self.embedded.contains(&column)
+    }
+
+    fn keeps_base(&self, name: &str) -> bool {
+        match name {
+            "pclass" => !self.embeds(Categorical::Pclass),
+            "sex" => !self.embeds(Categorical::Sex),
+            _ => true,
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let config = &self.config;
+        let mut names: Vec<String> = BASE_FEATURES
+            .iter()
+            .filter(|name| self.keeps_base(name))
+            .map(|n| n.to_string())
+            .collect();
+        let prefixed = |prefix: &str, values: &[&str]| -> Vec<String> {
+            values.iter().map(|v| format!("{}_{}", prefix, v)).collect()
+        };
+
+        if config.title && !self.embeds(Categorical::Title) {
+            names.extend(prefixed("title", &TITLES));
+        }
+        if config.deck && !self.embeds(Categorical::Deck) {
+            names.extend(prefixed("deck", &DECKS));
+        }
+        if config.cabin_count {
+            names.push("cabin_count".to_string());
+        }
+        if config.ticket_prefix {
+            names.extend(prefixed("ticket", &TICKET_PREFIXES));
+        }
+        if config.ticket_group_size {
+            names.push("ticket_group_size".to_string());
+        }
+        if config.family_size {
+            names.push("family_size".to_string());
+        }
+        if config.is_alone {
+            names.push("is_alone".to_string());
+        }
+        if config.embarked && !self.embeds(Categorical::Embarked) {
+            names.extend(prefixed("embarked", &PORTS));
+        }
+        names
+    }
+
+    /// The one place a record becomes model input, shared by training, validation and inference.
+    /// Expects a record already filled in by the `Imputer`.
+    pub fn extract(&self, record: &TitanicRecord) -> Vec<f32> {
+        let config = &self.config;
+        let age = record.age.expect("age should be imputed");
+        let class = record.pclass as f32;
+        let fare = record.fare.expect("fare should be imputed");
+        let sex: f32 = if record.sex == "male" { 1.0 } else { 0.0 };
+        let parch = record.parch as f32;
+        let sibsp = record.sib_sp as f32;
+        let family_size = sibsp + parch + 1.0;
+
+        let mut features: Vec<f32> = BASE_FEATURES
+            .iter()
+            .zip([age, class, fare, sex, parch, sibsp])
+            .filter(|(name, _)| self.keeps_base(name))
+            .map(|(_, value)| value)
+            .collect();
+
+        if config.title && !self.embeds(Categorical::Title) {
+            one_hot(&mut features, &TITLES, title(&record.name));
+        }
+        if config.deck && !self.embeds(Categorical::Deck) {
+            one_hot(&mut features, &DECKS, deck(record.cabin.as_deref()));
+        }
+        if config.cabin_count {
+            features.push(cabin_count(record.cabin.as_deref()) as f32);
+        }
+        if config.ticket_prefix {
+            one_hot(
+                &mut features,
+                &TICKET_PREFIXES,
+                ticket_prefix(&record.ticket),
+            );
+        }
+        if config.ticket_group_size {
+            features.push(self.ticket_group_size(record) as f32);
+        }
+        if config.family_size {
+            features.push(family_size);
+        }
+        if config.is_alone {
+            features.push(if family_size == 1.0 { 1.0 } else { 0.0 });
+        }
+        if config.embarked && !self.embeds(Categorical::Embarked) {
+            let port = record
+                .embarked
+                .as_deref()
+                .expect("embarked should be imputed");
+            one_hot(&mut features, &PORTS, &port.to_ascii_lowercase());
+        }
+
+        features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_titles_decks_and_ticket_prefixes() {
+        assert_eq!(title("Braund, Mr. Owen Harris"), "mr");
+        assert_eq!(title("Aubart, Mme. Leontine Pauline"), "mrs");
+        assert_eq!(title("Rothes, the Countess. of (Lucy Noel Martha)"), "rare");
+        assert_eq!(deck(Some("C23 C25 C27")), "c");
+        assert_eq!(deck(Some("T")), "unknown");
+        assert_eq!(cabin_count(Some("C23 C25 C27")), 3);
+        assert_eq!(ticket_prefix("A/5 21171"), "a5");
+        assert_eq!(ticket_prefix("STON/O 2. 3101292"), "stono2");
+        assert_eq!(ticket_prefix("349909"), "none");
+        assert_eq!(ticket_prefix("LINE"), "other");
+    }
+
+    #[test]
+    fn disabled_features_are_not_kept() {
+        let mut config = FeatureConfig::new();
+        config.set("deck", false).unwrap();
+        assert!(!config.keeps(&Categorical::Deck));
+        assert!(config.keeps(&Categorical::Title));
+        assert!(config.keeps(&Categorical::Sex));
+    }
+
+    #[test]
+    fn ticket_group_counts_unseen_passengers_once() {
+        let train = vec![TitanicRecord::example(1, 0), TitanicRecord::example(2, 1)];
+        let extractor = FeatureExtractor::fit(&FeatureConfig::new(), &[], &train);
+
+        assert_eq!(extractor.ticket_group_size(&train[0]), 2);
+        assert_eq!(
+            extractor.ticket_group_size(&TitanicRecord::example(3, 0)),
+            3
+        );
+
+        let mut alone = TitanicRecord::example(4, 0);
+        alone.ticket = "349909".to_string();
+        assert_eq!(extractor.ticket_group_size(&alone), 1);
+    }
+}
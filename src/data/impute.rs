@@ -0,0 +1,418 @@
+use std::{collections::HashMap, hash::Hash, str::FromStr};
+
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+use super::{quantile, title, TitanicRecord};
+
+/// How a missing numeric value (age or fare) is filled in. Every statistic is computed on the
+/// training split.
+#[derive(Config, Debug, PartialEq)]
+pub enum ImputeStrategy {
+    Mean,
+    Median,
+    Mode,
+    // Median of the passengers sharing pclass, sex and title, falling back to pclass and sex,
+    // then to the overall median.
+    GroupMedian,
+    Constant { value: f32 },
+    // Mean of the k nearest training passengers by class, sex, family and title.
+    Knn { k: usize },
+}
+
+impl FromStr for ImputeStrategy {
+    type Err = String;
+
+    /// `mean`, `median`, `mode`, `group-median`, `constant:<value>` or `knn:<k>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (s, None),
+        };
+
+        match (name, argument) {
+            ("mean", None) => Ok(ImputeStrategy::Mean),
+            ("median", None) => Ok(ImputeStrategy::Median),
+            ("mode", None) => Ok(ImputeStrategy::Mode),
+            ("group-median", None) => Ok(ImputeStrategy::GroupMedian),
+            ("constant", Some(value)) => value
+                .parse()
+                .map(|value| ImputeStrategy::Constant { value })
+                .map_err(|_| format!("invalid constant {}", value)),
+            ("knn", Some(k)) => k
+                .parse()
+                .map(|k| ImputeStrategy::Knn { k })
+                .map_err(|_| format!("invalid k {}", k)),
+            _ => Err(format!(
+                "unknown strategy {}, expected mean, median, mode, group-median, constant:<value> or knn:<k>",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Config, Debug, PartialEq)]
+pub enum CategoricalStrategy {
+    Mode,
+    Constant { value: String },
+}
+
+#[derive(Config, Debug)]
+pub struct ImputeConfig {
+    #[config(default = "ImputeStrategy::GroupMedian")]
+    pub age: ImputeStrategy,
+
+    #[config(default = "ImputeStrategy::Median")]
+    pub fare: ImputeStrategy,
+
+    #[config(default = "CategoricalStrategy::Mode")]
+    pub embarked: CategoricalStrategy,
+
+    // Adds age_missing, fare_missing and embarked_missing columns.
+    #[config(default = false)]
+    pub missing_indicators: bool,
+}
+
+/// The most frequent values, smallest first when there is a tie.
+pub fn modes<T: Hash + Eq + Ord + Clone>(values: &[T]) -> Vec<T> {
+    let mut map = HashMap::new();
+    for value in values {
+        let count = map.entry(value).or_insert(0);
+        *count += 1;
+    }
+
+    let max_value = map.values().cloned().max().unwrap_or(0);
+
+    let mut modes: Vec<T> = map
+        .into_iter()
+        .filter(|&(_, v)| v == max_value)
+        .map(|(k, _)| k.clone())
+        .collect();
+    modes.sort();
+    modes
+}
+
+fn median(values: &[f32]) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    quantile(&sorted, 0.5)
+}
+
+fn group_keys(record: &TitanicRecord) -> [String; 2] {
+    [
+        format!("{}|{}|{}", record.pclass, record.sex, title(&record.name)),
+        format!("{}|{}", record.pclass, record.sex),
+    ]
+}
+
+fn knn_inputs(record: &TitanicRecord) -> Vec<f32> {
+    let title = title(&record.name);
+    vec![
+        record.pclass as f32,
+        if record.sex == "male" { 1.0 } else { 0.0 },
+        record.sib_sp as f32,
+        record.parch as f32,
+        if title == "master" { 1.0 } else { 0.0 },
+        if title == "miss" { 1.0 } else { 0.0 },
+        if title == "mrs" { 1.0 } else { 0.0 },
+    ]
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum NumericImputer {
+    Constant(f32),
+    Grouped {
+        groups: HashMap<String, f32>,
+        fallback: f32,
+    },
+    Knn {
+        k: usize,
+        scale: Vec<f32>,
+        inputs: Vec<Vec<f32>>,
+        values: Vec<f32>,
+    },
+}
+
+impl NumericImputer {
+    fn fit(
+        strategy: &ImputeStrategy,
+        records: &[TitanicRecord],
+        value: impl Fn(&TitanicRecord) -> Option<f32>,
+    ) -> NumericImputer {
+        let known: Vec<&TitanicRecord> = records.iter().filter(|r| value(r).is_some()).collect();
+        let values: Vec<f32> = known.iter().filter_map(|r| value(r)).collect();
+        if values.is_empty() {
+            return NumericImputer::Constant(0.0);
+        }
+
+        match strategy {
+            ImputeStrategy::Mean => {
+                NumericImputer::Constant(values.iter().sum::<f32>() / values.len() as f32)
+            }
+            ImputeStrategy::Median => NumericImputer::Constant(median(&values)),
+            ImputeStrategy::Mode => {
+                // ages like 0.42 or fares like 7.8292 rarely repeat exactly, so count whole units
+                let rounded: Vec<i64> = values.iter().map(|v| v.round() as i64).collect();
+                NumericImputer::Constant(modes(&rounded)[0] as f32)
+            }
+            ImputeStrategy::Constant { value } => NumericImputer::Constant(*value),
+            ImputeStrategy::GroupMedian => {
+                let mut grouped: HashMap<String, Vec<f32>> = HashMap::new();
+                for record in known.iter() {
+                    for key in group_keys(record) {
+                        grouped.entry(key).or_default().push(value(record).unwrap());
+                    }
+                }
+                let groups = grouped
+                    .into_iter()
+                    .map(|(key, values)| (key, median(&values)))
+                    .collect();
+
+                NumericImputer::Grouped {
+                    groups,
+                    fallback: median(&values),
+                }
+            }
+            ImputeStrategy::Knn { k } => {
+                let inputs: Vec<Vec<f32>> = known.iter().map(|r| knn_inputs(r)).collect();
+                let n = inputs.len() as f32;
+                let scale = (0..inputs[0].len())
+                    .map(|i| {
+                        let mean = inputs.iter().map(|x| x[i]).sum::<f32>() / n;
+                        let variance =
+                            inputs.iter().map(|x| (x[i] - mean).powi(2)).sum::<f32>() / n;
+                        if variance > 0.0 {
+                            variance.sqrt()
+                        } else {
+                            1.0
+                        }
+                    })
+                    .collect();
+
+                NumericImputer::Knn {
+                    k: (*k).max(1),
+                    scale,
+                    inputs,
+                    values,
+                }
+            }
+        }
+    }
+
+    fn fill(&self, record: &TitanicRecord) -> f32 {
+        match self {
+            NumericImputer::Constant(value) => *value,
+            NumericImputer::Grouped { groups, fallback } => group_keys(record)
+                .iter()
+                .find_map(|key| groups.get(key))
+                .copied()
+                .unwrap_or(*fallback),
+            NumericImputer::Knn {
+                k,
+                scale,
+                inputs,
+                values,
+            } => {
+                let target = knn_inputs(record);
+                let mut distances: Vec<(f32, f32)> = inputs
+                    .iter()
+                    .zip(values)
+                    .map(|(input, value)| {
+                        let distance = input
+                            .iter()
+                            .zip(&target)
+                            .zip(scale)
+                            .map(|((a, b), s)| ((a - b) / s).powi(2))
+                            .sum::<f32>();
+                        (distance, *value)
+                    })
+                    .collect();
+                distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let neighbours = &distances[..(*k).min(distances.len())];
+                neighbours.iter().map(|(_, value)| value).sum::<f32>() / neighbours.len() as f32
+            }
+        }
+    }
+}
+
+/// Missing value imputation fitted on the training split and stored in the model bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Imputer {
+    pub config: ImputeConfig,
+    pub age: NumericImputer,
+    pub fare: NumericImputer,
+    pub embarked: String,
+}
+
+impl Imputer {
+    pub fn fit(config: &ImputeConfig, records: &[TitanicRecord]) -> Imputer {
+        let age = NumericImputer::fit(&config.age, records, |r| r.age);
+        let fare = NumericImputer::fit(&config.fare, records, |r| r.fare);
+        let embarked = match &config.embarked {
+            CategoricalStrategy::Mode => {
+                let ports: Vec<String> =
+                    records.iter().filter_map(|r| r.embarked.clone()).collect();
+                modes(&ports)
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| "S".to_string())
+            }
+            CategoricalStrategy::Constant { value } => value.clone(),
+        };
+
+        Imputer {
+            config: config.clone(),
+            age,
+            fare,
+            embarked,
+        }
+    }
+
+    /// A copy of the record with every imputed field filled in.
+    pub fn fill(&self, record: &TitanicRecord) -> TitanicRecord {
+        let mut filled = record.clone();
+        filled.age = Some(record.age.unwrap_or_else(|| self.age.fill(record)));
+        filled.fare = Some(record.fare.unwrap_or_else(|| self.fare.fill(record)));
+        filled.embarked = Some(
+            record
+                .embarked
+                .clone()
+                .unwrap_or_else(|| self.embarked.clone()),
+        );
+        filled
+    }
+
+    pub fn indicator_names(&self) -> Vec<String> {
+        if !self.config.missing_indicators {
+            return Vec::new();
+        }
+        ["age_missing", "fare_missing", "embarked_missing"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    pub fn indicators(&self, record: &TitanicRecord) -> Vec<f32> {
+        if !self.config.missing_indicators {
+            return Vec::new();
+        }
+        [
+            record.age.is_none(),
+            record.fare.is_none(),
+            record.embarked.is_none(),
+        ]
+        .iter()
+        .map(|missing| if *missing { 1.0 } else { 0.0 })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passenger(id: u32, name: &str, pclass: u8, age: Option<f32>) -> TitanicRecord {
+        let mut record = TitanicRecord::example(id, 0);
+        record.name = name.to_string();
+        record.pclass = pclass;
+        record.age = age;
+        record
+    }
+
+    fn fill_age(
+        strategy: ImputeStrategy,
+        records: &[TitanicRecord],
+        record: &TitanicRecord,
+    ) -> f32 {
+        NumericImputer::fit(&strategy, records, |r| r.age).fill(record)
+    }
+
+    #[test]
+    fn modes_are_sorted_on_ties() {
+        assert_eq!(modes(&[3, 1, 3, 1, 2]), vec![1, 3]);
+        assert_eq!(modes(&["S", "C", "S"]), vec!["S"]);
+        assert!(modes::<u8>(&[]).is_empty());
+    }
+
+    #[test]
+    fn fills_constant_statistics() {
+        let records: Vec<TitanicRecord> = [Some(20.0), Some(30.0), Some(30.4), Some(70.0), None]
+            .into_iter()
+            .enumerate()
+            .map(|(i, age)| passenger(i as u32, "Braund, Mr. Owen", 3, age))
+            .collect();
+        let missing = &records[4];
+
+        assert!((fill_age(ImputeStrategy::Mean, &records, missing) - 37.6).abs() < 1e-4);
+        assert!((fill_age(ImputeStrategy::Median, &records, missing) - 30.2).abs() < 1e-4);
+        assert_eq!(fill_age(ImputeStrategy::Mode, &records, missing), 30.0);
+        assert_eq!(
+            fill_age(ImputeStrategy::Constant { value: -1.0 }, &records, missing),
+            -1.0
+        );
+    }
+
+    #[test]
+    fn group_median_falls_back_to_wider_groups() {
+        let records = vec![
+            passenger(1, "Palsson, Master. Gosta", 3, Some(2.0)),
+            passenger(2, "Palsson, Master. Paul", 3, Some(4.0)),
+            passenger(3, "Braund, Mr. Owen", 3, Some(30.0)),
+            passenger(4, "Cumings, Mr. John", 1, Some(50.0)),
+        ];
+        let fill = |name, pclass| {
+            fill_age(
+                ImputeStrategy::GroupMedian,
+                &records,
+                &passenger(5, name, pclass, None),
+            )
+        };
+
+        assert_eq!(fill("Rice, Master. Eugene", 3), 3.0);
+        // no first class master, the first class men instead
+        assert_eq!(fill("Carter, Master. William", 1), 50.0);
+        // no second class men at all, the overall median
+        assert_eq!(fill("Hickman, Mr. Lewis", 2), 17.0);
+    }
+
+    #[test]
+    fn knn_averages_the_nearest_passengers() {
+        let records = vec![
+            passenger(1, "Palsson, Master. Gosta", 3, Some(2.0)),
+            passenger(2, "Palsson, Master. Paul", 3, Some(4.0)),
+            passenger(3, "Cumings, Mr. John", 1, Some(50.0)),
+        ];
+        let missing = passenger(4, "Rice, Master. Eugene", 3, None);
+        assert_eq!(
+            fill_age(ImputeStrategy::Knn { k: 2 }, &records, &missing),
+            3.0
+        );
+    }
+
+    #[test]
+    fn fills_every_missing_field() {
+        let mut missing = passenger(3, "Braund, Mr. Owen", 3, None);
+        missing.fare = None;
+        missing.embarked = None;
+        let mut cherbourg = TitanicRecord::example(2, 0);
+        cherbourg.embarked = Some("C".to_string());
+        let records = vec![TitanicRecord::example(1, 0), cherbourg, missing.clone()];
+
+        let filled = Imputer::fit(&ImputeConfig::new(), &records).fill(&missing);
+        assert_eq!(filled.age, Some(22.0));
+        assert_eq!(filled.fare, Some(7.25));
+        assert_eq!(filled.embarked.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn parses_strategies() {
+        assert_eq!("knn:5".parse(), Ok(ImputeStrategy::Knn { k: 5 }));
+        assert_eq!(
+            "constant:28".parse(),
+            Ok(ImputeStrategy::Constant { value: 28.0 })
+        );
+        assert!("knn".parse::<ImputeStrategy>().is_err());
+        assert!("median:3".parse::<ImputeStrategy>().is_err());
+    }
+}
@@ -1,8 +1,26 @@
 mod raw;
 pub use raw::*;
 
+mod categorical;
+pub use categorical::*;
+
+mod features;
+pub use features::*;
+
 mod classified;
 pub use classified::*;
 
 mod test;
 pub use test::*;
+
+mod impute;
+pub use impute::*;
+
+mod preprocess;
+pub use preprocess::*;
+
+mod pipeline;
+pub use pipeline::*;
+
+mod split;
+pub use split::*;
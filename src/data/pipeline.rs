@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Categorical, CategoryEncoder, FeatureConfig, FeatureExtractor, ImputeConfig, Imputer,
+    PreprocessConfig, Preprocessor, TitanicRecord,
+};
+
+/// Every step between a csv record and the model input, fitted on the training split and
+/// stored in the model bundle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub imputer: Imputer,
+    pub extractor: FeatureExtractor,
+    pub preprocessor: Preprocessor,
+    #[serde(default)]
+    pub encoder: CategoryEncoder,
+}
+
+impl Pipeline {
+    pub fn fit(
+        features: &FeatureConfig,
+        embedded: &[Categorical],
+        impute: &ImputeConfig,
+        preprocess: &PreprocessConfig,
+        records: &[TitanicRecord],
+    ) -> Pipeline {
+        let imputer = Imputer::fit(impute, records);
+        let extractor = FeatureExtractor::fit(features, embedded, records);
+        let filled: Vec<TitanicRecord> = records.iter().map(|r| imputer.fill(r)).collect();
+        let encoder = CategoryEncoder::fit(embedded, &filled);
+        let mut pipeline = Pipeline {
+            imputer,
+            extractor,
+            preprocessor: Preprocessor::identity(0),
+            encoder,
+        };
+
+        let rows: Vec<Vec<f32>> = records.iter().map(|r| pipeline.extract(r)).collect();
+        pipeline.preprocessor = Preprocessor::fit(
+            preprocess,
+            &pipeline.names(),
+            rows.iter().map(|row| row.as_slice()),
+        );
+        pipeline
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.extractor.names();
+        names.extend(self.imputer.indicator_names());
+        names
+    }
+
+    /// Unscaled features of a record.
+    fn extract(&self, record: &TitanicRecord) -> Vec<f32> {
+        let filled = self.imputer.fill(record);
+        let mut feature = self.extractor.extract(&filled);
+        feature.extend(self.imputer.indicators(record));
+        feature
+    }
+
+    pub fn transform(&self, record: &TitanicRecord) -> Vec<f32> {
+        let mut feature = self.extract(record);
+        self.preprocessor.transform(&mut feature);
+        feature
+    }
+
+    /// Vocabulary indices of the embedded columns.
+    pub fn categories(&self, record: &TitanicRecord) -> Vec<usize> {
+        self.encoder.encode(&self.imputer.fill(record))
+    }
+}
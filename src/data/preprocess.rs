@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+#[derive(Config, Debug, PartialEq)]
+pub enum Scaler {
+    None,
+    Standard,
+    MinMax,
+    Robust,
+}
+
+impl FromStr for Scaler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Scaler::None),
+            "standard" => Ok(Scaler::Standard),
+            "min-max" => Ok(Scaler::MinMax),
+            "robust" => Ok(Scaler::Robust),
+            _ => Err(format!(
+                "unknown scaler {}, expected none, standard, min-max or robust",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Config, Debug)]
+pub struct PreprocessConfig {
+    #[config(default = "Scaler::Standard")]
+    pub scaler: Scaler,
+
+    // Fares are heavily right skewed (most under 30, a few above 500).
+    #[config(default = true)]
+    pub log_fare: bool,
+}
+
+/// Maps a raw value to `(value - shift) / scale`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ColumnScaler {
+    pub shift: f32,
+    pub scale: f32,
+}
+
+impl ColumnScaler {
+    fn identity() -> ColumnScaler {
+        ColumnScaler {
+            shift: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    fn fit(scaler: &Scaler, values: &mut [f32]) -> ColumnScaler {
+        if values.is_empty() {
+            return Self::identity();
+        }
+
+        let column = match scaler {
+            Scaler::None => Self::identity(),
+            Scaler::Standard => {
+                let n = values.len() as f32;
+                let mean = values.iter().sum::<f32>() / n;
+                let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+                ColumnScaler {
+                    shift: mean,
+                    scale: variance.sqrt(),
+                }
+            }
+            Scaler::MinMax => {
+                let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+                let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                ColumnScaler {
+                    shift: min,
+                    scale: max - min,
+                }
+            }
+            Scaler::Robust => {
+                values.sort_by(|a, b| a.total_cmp(b));
+                ColumnScaler {
+                    shift: quantile(values, 0.5),
+                    scale: quantile(values, 0.75) - quantile(values, 0.25),
+                }
+            }
+        };
+
+        // Constant columns would divide by zero, leave them centered instead.
+        if column.scale.abs() < f32::EPSILON {
+            return ColumnScaler {
+                shift: column.shift,
+                scale: 1.0,
+            };
+        }
+        column
+    }
+
+    fn transform(&self, value: f32) -> f32 {
+        (value - self.shift) / self.scale
+    }
+}
+
+/// Linear interpolation between the closest ranks of an already sorted slice.
+pub fn quantile(sorted: &[f32], q: f32) -> f32 {
+    let position = q * (sorted.len() - 1) as f32;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let weight = position - lower as f32;
+    sorted[lower] * (1.0 - weight) + sorted[upper] * weight
+}
+
+/// Preprocessing fitted on the training split and stored in the model bundle, so inference
+/// scales features exactly like training did.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preprocessor {
+    pub log_columns: Vec<usize>,
+    pub columns: Vec<ColumnScaler>,
+}
+
+impl Preprocessor {
+    pub fn identity(len: usize) -> Preprocessor {
+        Preprocessor {
+            log_columns: Vec::new(),
+            columns: vec![ColumnScaler::identity(); len],
+        }
+    }
+
+    pub fn fit<'a>(
+        config: &PreprocessConfig,
+        names: &[String],
+        features: impl Iterator<Item = &'a [f32]>,
+    ) -> Preprocessor {
+        let log_columns: Vec<usize> = if config.log_fare {
+            names.iter().position(|name| name == "fare")
+        } else {
+            None
+        }
+        .into_iter()
+        .collect();
+
+        let mut columns = vec![Vec::new(); names.len()];
+        for feature in features {
+            for (i, value) in feature.iter().enumerate() {
+                let value = if log_columns.contains(&i) {
+                    value.ln_1p()
+                } else {
+                    *value
+                };
+                columns[i].push(value);
+            }
+        }
+
+        let columns = columns
+            .iter_mut()
+            .map(|values| ColumnScaler::fit(&config.scaler, values))
+            .collect();
+
+        Preprocessor {
+            log_columns,
+            columns,
+        }
+    }
+
+    pub fn transform(&self, feature: &mut [f32]) {
+        for (i, value) in feature.iter_mut().enumerate() {
+            if self.log_columns.contains(&i) {
+                *value = value.ln_1p();
+            }
+            *value = self.columns[i].transform(*value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(scaler: Scaler) -> (f32, f32) {
+        let column = ColumnScaler::fit(&scaler, &mut [5.0, 1.0, 4.0, 2.0, 3.0]);
+        (column.shift, column.scale)
+    }
+
+    #[test]
+    fn fits_every_scaler() {
+        assert_eq!(fit(Scaler::None), (0.0, 1.0));
+        assert_eq!(fit(Scaler::Standard), (3.0, 2f32.sqrt()));
+        assert_eq!(fit(Scaler::MinMax), (1.0, 4.0));
+        assert_eq!(fit(Scaler::Robust), (3.0, 2.0));
+    }
+
+    #[test]
+    fn constant_columns_are_only_centered() {
+        let column = ColumnScaler::fit(&Scaler::Standard, &mut [7.0, 7.0, 7.0]);
+        assert_eq!((column.shift, column.scale), (7.0, 1.0));
+        assert_eq!(column.transform(7.0), 0.0);
+    }
+
+    #[test]
+    fn quantiles_interpolate_between_ranks() {
+        let sorted = [1.0, 2.0, 4.0, 8.0];
+        assert_eq!(quantile(&sorted, 0.0), 1.0);
+        assert_eq!(quantile(&sorted, 0.5), 3.0);
+        assert_eq!(quantile(&sorted, 1.0), 8.0);
+        assert_eq!(quantile(&[5.0], 0.25), 5.0);
+    }
+
+    #[test]
+    fn logs_the_fare_before_scaling() {
+        let config = PreprocessConfig::new().with_scaler(Scaler::MinMax);
+        let names = vec!["age".to_string(), "fare".to_string()];
+        let rows = [vec![20.0, 0.0], vec![40.0, 1f32.exp() - 1.0]];
+        let preprocessor = Preprocessor::fit(&config, &names, rows.iter().map(|r| r.as_slice()));
+        assert_eq!(preprocessor.log_columns, vec![1]);
+
+        let mut feature = vec![30.0, 1f32.exp() - 1.0];
+        preprocessor.transform(&mut feature);
+        assert_eq!(feature[0], 0.5);
+        assert!((feature[1] - 1.0).abs() < 1e-6);
+    }
+}
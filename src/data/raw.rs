@@ -1,66 +1,26 @@
-#![allow(dead_code)]
-use std::collections::HashMap;
+use std::path::Path;
 
 use plotly::{layout::Axis, Bar, Layout, Plot, Scatter};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct TitanicRecord {
     pub passenger_id: u32,
-    pub survived: u8,
+    // missing from the kaggle test csv
+    #[serde(default)]
+    pub survived: Option<u8>,
     pub pclass: u8,
     pub name: String,
     pub sex: String,
-    #[serde(deserialize_with = "option_float_to_int")]
-    pub age: u32,
+    // missing values are filled in by the fitted `Imputer`
+    pub age: Option<f32>,
     pub sib_sp: u8,
     pub parch: u8,
     pub ticket: String,
-    pub fare: f32,
-    #[serde(deserialize_with = "option_string_to_string_cabin")]
-    pub cabin: String,
-    #[serde(deserialize_with = "option_string_to_string_embark")]
-    pub embarked: String,
-}
-
-fn option_float_to_int<'de, D>(deserializer: D) -> Result<u32, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Option::<f32>::deserialize(deserializer).map(|record| {
-        if let Some(age) = record {
-            age as u32
-        } else {
-            24 as u32
-        }
-    })
-}
-
-fn option_string_to_string_embark<'de, D>(deserializer: D) -> Result<String, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Option::<String>::deserialize(deserializer).map(|record| {
-        if let Some(embarked) = record {
-            embarked
-        } else {
-            "S".to_string()
-        }
-    })
-}
-
-fn option_string_to_string_cabin<'de, D>(deserializer: D) -> Result<String, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Option::<String>::deserialize(deserializer).map(|record| {
-        if let Some(cabin) = record {
-            cabin
-        } else {
-            "C23 C25 C27".to_string()
-        }
-    })
+    pub fare: Option<f32>,
+    pub cabin: Option<String>,
+    pub embarked: Option<String>,
 }
 
 pub struct RawData(Vec<TitanicRecord>);
@@ -70,6 +30,16 @@ impl RawData {
         RawData(Self::collect_records(path))
     }
 
+    /// Several csv files with the same columns read as one, e.g. `train.csv` and `validation.csv`.
+    pub fn from_paths(paths: &[String]) -> RawData {
+        RawData(
+            paths
+                .iter()
+                .flat_map(|path| Self::collect_records(path))
+                .collect(),
+        )
+    }
+
     pub fn get_all_rows(&self) -> &Vec<TitanicRecord> {
         &self.0
     }
@@ -86,34 +56,11 @@ impl RawData {
     }
 
     pub fn get_ages(&self) -> Vec<f32> {
-        self.0.iter().map(|record| record.age as f32).collect()
-    }
-
-    pub fn get_survived(&self) -> Vec<f32> {
-        self.0.iter().map(|record| record.survived as f32).collect()
-    }
-
-    pub fn get_classes(&self) -> Vec<f32> {
-        self.0.iter().map(|record| record.pclass as f32).collect()
+        self.0.iter().filter_map(|record| record.age).collect()
     }
 
     pub fn get_fares(&self) -> Vec<f32> {
-        self.0.iter().map(|record| record.fare).collect()
-    }
-
-    pub fn get_sexes(&self) -> Vec<f32> {
-        self.0
-            .iter()
-            .map(|record| if record.sex == "male" { 1.0 } else { 0.0 })
-            .collect()
-    }
-
-    pub fn get_parch(&self) -> Vec<f32> {
-        self.0.iter().map(|record| record.parch as f32).collect()
-    }
-
-    pub fn get_sibsp(&self) -> Vec<f32> {
-        self.0.iter().map(|record| record.sib_sp as f32).collect()
+        self.0.iter().filter_map(|record| record.fare).collect()
     }
 }
 
@@ -122,17 +69,35 @@ pub struct Visualizer {
 }
 
 impl Visualizer {
-    pub fn new() -> Visualizer {
+    pub fn new(path: &str) -> Visualizer {
         Visualizer {
-            store: RawData::new("data/train.csv"),
+            store: RawData::new(path),
         }
     }
 
-    fn survive_by_class_bar_chart(&self) {
-        let mut class_survival_count = vec![(0, 0, 0); 3]; // (class, survived, not_survived)
+    pub fn render(&self, output_dir: &str) {
+        let output_dir = Path::new(output_dir);
+        std::fs::create_dir_all(output_dir).expect("could not create output directory");
+
+        self.survive_by_class_bar_chart(output_dir);
+        self.age_histogram(output_dir);
+        self.survive_by_sex_bar_chart(output_dir);
+        self.fare_histogram(output_dir);
+        self.survive_by_age_bar_chart(output_dir);
+        let (fares, ages): (Vec<f32>, Vec<f32>) = self
+            .store
+            .0
+            .iter()
+            .filter_map(|record| Some((record.fare?, record.age?)))
+            .unzip();
+        Self::scatter_plot(&fares, &ages, "Fares vs Ages", "Fares", "Ages", output_dir);
+    }
+
+    fn survive_by_class_bar_chart(&self, output_dir: &Path) {
+        let mut class_survival_count = [(0, 0, 0); 3]; // (class, survived, not_survived)
         for record in &self.store.0 {
             let class = record.pclass as usize - 1;
-            if record.survived == 1 {
+            if record.survived == Some(1) {
                 class_survival_count[class].1 += 1;
             } else {
                 class_survival_count[class].2 += 1;
@@ -163,10 +128,10 @@ impl Visualizer {
         plot.add_trace(trace2);
         plot.set_layout(layout);
 
-        plot.write_html("survive_by_class_bar_chart.html");
+        plot.write_html(output_dir.join("survive_by_class_bar_chart.html"));
     }
 
-    fn age_histogram(&self) {
+    fn age_histogram(&self, output_dir: &Path) {
         // Extract ages and filter out None values
         let ages = self.store.get_ages();
 
@@ -197,10 +162,10 @@ impl Visualizer {
         plot.set_layout(layout);
 
         // Save the plot to an HTML file
-        plot.write_html("age_histogram.html");
+        plot.write_html(output_dir.join("age_histogram.html"));
     }
 
-    fn survive_by_sex_bar_chart(&self) {
+    fn survive_by_sex_bar_chart(&self, output_dir: &Path) {
         // Calculate survival rates by sex
         let mut male_count = 0;
         let mut male_survived = 0;
@@ -210,12 +175,12 @@ impl Visualizer {
         for record in &self.store.0 {
             if record.sex == "male" {
                 male_count += 1;
-                if record.survived == 1 {
+                if record.survived == Some(1) {
                     male_survived += 1;
                 }
             } else if record.sex == "female" {
                 female_count += 1;
-                if record.survived == 1 {
+                if record.survived == Some(1) {
                     female_survived += 1;
                 }
             }
@@ -241,10 +206,10 @@ impl Visualizer {
         plot.set_layout(layout);
 
         // Save the plot to an HTML file
-        plot.write_html("survival_rate_by_sex.html");
+        plot.write_html(output_dir.join("survival_rate_by_sex.html"));
     }
 
-    fn fare_histogram(&self) {
+    fn fare_histogram(&self, output_dir: &Path) {
         // Extract fares
         let fares = self.store.get_fares();
 
@@ -282,21 +247,23 @@ impl Visualizer {
         plot.set_layout(layout);
 
         // Save the plot to an HTML file
-        plot.write_html("fare_histogram.html");
+        plot.write_html(output_dir.join("fare_histogram.html"));
     }
 
-    fn survive_by_age_bar_chart(&self) {
+    fn survive_by_age_bar_chart(&self, output_dir: &Path) {
         // Define age bins and calculate survival rates
-        let bin_size = 10;
+        let bin_size = 10.0;
         let num_bins = 8;
         let mut age_bins = vec![(0, 0); num_bins]; // (total, survived)
 
         for record in &self.store.0 {
-            let age = record.age;
+            let Some(age) = record.age else {
+                continue;
+            };
             let bin_index = (age / bin_size) as usize;
             if bin_index < age_bins.len() {
                 age_bins[bin_index].0 += 1;
-                if record.survived == 1 {
+                if record.survived == Some(1) {
                     age_bins[bin_index].1 += 1;
                 }
             }
@@ -332,16 +299,22 @@ impl Visualizer {
         plot.set_layout(layout);
 
         // Save the plot to an HTML file
-        plot.write_html("survival_rate_by_age.html");
+        plot.write_html(output_dir.join("survival_rate_by_age.html"));
     }
 
-    fn scatter_plot(x_vals: &[f32], y_vals: &[f32], title: &str, x_label: &str, y_label: &str) {
+    fn scatter_plot(
+        x_vals: &[f32],
+        y_vals: &[f32],
+        title: &str,
+        x_label: &str,
+        y_label: &str,
+        output_dir: &Path,
+    ) {
         let coef = Self::pearson_correlation(x_vals, y_vals);
 
         // Create the scatter plot
         let x = x_vals.to_vec();
         let y = y_vals.to_vec();
-        dbg!(x.len(), y.len());
         let trace = Scatter::new(x, y)
             .name(title)
             .mode(plotly::common::Mode::Markers);
@@ -357,41 +330,11 @@ impl Visualizer {
 
         // Save the plot to an HTML file
 
-        plot.write_html(format!(
+        plot.write_html(output_dir.join(format!(
             "{}_{}_scatter.html",
             x_label.to_lowercase(),
             y_label.to_lowercase()
-        ));
-    }
-
-    fn mode_nums(numbers: &Vec<u32>) -> Vec<u32> {
-        let mut map = HashMap::new();
-        for integer in numbers {
-            let count = map.entry(integer).or_insert(0);
-            *count += 1;
-        }
-
-        let max_value = map.values().cloned().max().unwrap_or(0);
-
-        map.into_iter()
-            .filter(|&(_, v)| v == max_value)
-            .map(|(&k, _)| k)
-            .collect()
-    }
-
-    fn mode_strs(strings: Vec<String>) -> Vec<String> {
-        let mut map = HashMap::new();
-        for string in strings {
-            let count = map.entry(string).or_insert(0);
-            *count += 1;
-        }
-
-        let max_value = map.values().cloned().max().unwrap_or(0);
-
-        map.into_iter()
-            .filter(|&(_, v)| v == max_value)
-            .map(|(k, _)| k)
-            .collect()
+        )));
     }
 
     fn pearson_correlation(x: &[f32], y: &[f32]) -> f32 {
@@ -421,3 +364,24 @@ impl Visualizer {
         covariance / (stddev_x * stddev_y)
     }
 }
+
+#[cfg(test)]
+impl TitanicRecord {
+    /// A third class passenger with every field known, for tests to adjust.
+    pub fn example(passenger_id: u32, survived: u8) -> TitanicRecord {
+        TitanicRecord {
+            passenger_id,
+            survived: Some(survived),
+            pclass: 3,
+            name: "Braund, Mr. Owen Harris".to_string(),
+            sex: "male".to_string(),
+            age: Some(22.0),
+            sib_sp: 1,
+            parch: 0,
+            ticket: "A/5 21171".to_string(),
+            fare: Some(7.25),
+            cabin: None,
+            embarked: Some("S".to_string()),
+        }
+    }
+}
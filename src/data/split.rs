@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use burn::config::Config;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use super::{RawData, TitanicRecord};
+
+/// Stratified train/validation split of a labeled csv. Passengers are always stratified by
+/// `survived`, optionally also by class and sex, so both halves keep the same proportions.
+#[derive(Config, Debug)]
+pub struct SplitConfig {
+    // Share of each stratum held out for validation.
+    #[config(default = 0.1)]
+    pub valid_ratio: f64,
+
+    #[config(default = false)]
+    pub by_pclass: bool,
+
+    #[config(default = false)]
+    pub by_sex: bool,
+}
+
+impl SplitConfig {
+    pub fn stratify_by(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "survived" => {}
+            "pclass" => self.by_pclass = true,
+            "sex" => self.by_sex = true,
+            _ => {
+                return Err(format!(
+                    "unknown stratum {}, expected survived, pclass or sex",
+                    name
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stratum(&self, record: &TitanicRecord) -> String {
+        let survived = record
+            .survived
+            .expect("stratified split needs a Survived column");
+        let mut key = survived.to_string();
+        if self.by_pclass {
+            key.push_str(&format!("|{}", record.pclass));
+        }
+        if self.by_sex {
+            key.push_str(&format!("|{}", record.sex));
+        }
+        key
+    }
+
+    /// Records grouped by stratum, each group shuffled with `seed`. The groups are ordered by key
+    /// so the same seed always gives the same partition.
+    pub fn strata(&self, records: &[TitanicRecord], seed: u64) -> Vec<Vec<TitanicRecord>> {
+        let mut strata: BTreeMap<String, Vec<TitanicRecord>> = BTreeMap::new();
+        for record in records {
+            strata
+                .entry(self.stratum(record))
+                .or_default()
+                .push(record.clone());
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        strata
+            .into_values()
+            .map(|mut stratum| {
+                stratum.shuffle(&mut rng);
+                stratum
+            })
+            .collect()
+    }
+
+    /// `(train, validation)`, both sorted by passenger id.
+    pub fn split(
+        &self,
+        records: &[TitanicRecord],
+        seed: u64,
+    ) -> (Vec<TitanicRecord>, Vec<TitanicRecord>) {
+        assert!(
+            self.valid_ratio > 0.0 && self.valid_ratio < 1.0,
+            "valid ratio must be between 0 and 1, got {}",
+            self.valid_ratio
+        );
+        let mut train = Vec::new();
+        let mut valid = Vec::new();
+        for mut stratum in self.strata(records, seed) {
+            let held_out = (stratum.len() as f64 * self.valid_ratio).round() as usize;
+            valid.extend(stratum.drain(..held_out));
+            train.extend(stratum);
+        }
+
+        train.sort_by_key(|record| record.passenger_id);
+        valid.sort_by_key(|record| record.passenger_id);
+        (train, valid)
+    }
+}
+
+fn survival_rate(records: &[TitanicRecord]) -> f64 {
+    let survived = records.iter().filter(|r| r.survived == Some(1)).count();
+    100.0 * survived as f64 / records.len().max(1) as f64
+}
+
+/// Splits labeled csv files and writes both partitions to disk.
+pub fn split_files(
+    input_paths: &[String],
+    train_path: &str,
+    valid_path: &str,
+    config: &SplitConfig,
+    seed: u64,
+) {
+    let raw_data = RawData::from_paths(input_paths);
+    let (train, valid) = config.split(raw_data.get_all_rows(), seed);
+
+    write_records(train_path, &train);
+    write_records(valid_path, &valid);
+
+    println!(
+        "Split {} passengers into {} for training ({:.1} % survived) and {} for validation ({:.1} % survived)",
+        raw_data.get_all_rows().len(),
+        train.len(),
+        survival_rate(&train),
+        valid.len(),
+        survival_rate(&valid),
+    );
+}
+
+pub fn write_records(path: &str, records: &[TitanicRecord]) {
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(dir).expect("could not create output directory");
+    }
+
+    let mut wtr = csv::Writer::from_path(path).expect("could not create file");
+    for record in records {
+        wtr.serialize(record).expect("could not write record");
+    }
+    wtr.flush().expect("could not write file");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<TitanicRecord> {
+        (1..=100)
+            .map(|id| TitanicRecord::example(id, (id % 4 == 0) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn split_keeps_the_survival_rate() {
+        let config = SplitConfig::new().with_valid_ratio(0.2);
+        let (train, valid) = config.split(&records(), 42);
+        assert_eq!(train.len(), 80);
+        assert_eq!(valid.len(), 20);
+        assert_eq!(survival_rate(&train), 25.0);
+        assert_eq!(survival_rate(&valid), 25.0);
+    }
+
+    #[test]
+    fn split_is_disjoint_and_sorted() {
+        let (train, valid) = SplitConfig::new().split(&records(), 42);
+        let mut ids: Vec<u32> = train.iter().chain(&valid).map(|r| r.passenger_id).collect();
+        assert!(train
+            .windows(2)
+            .all(|w| w[0].passenger_id < w[1].passenger_id));
+        assert!(valid
+            .windows(2)
+            .all(|w| w[0].passenger_id < w[1].passenger_id));
+        ids.sort();
+        assert_eq!(ids, (1..=100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn split_depends_only_on_the_seed() {
+        let ids = |seed| {
+            let (_, valid) = SplitConfig::new().split(&records(), seed);
+            valid.iter().map(|r| r.passenger_id).collect::<Vec<u32>>()
+        };
+        assert_eq!(ids(7), ids(7));
+        assert_ne!(ids(7), ids(8));
+    }
+
+    #[test]
+    fn stratifies_by_class_and_sex() {
+        let mut records = records();
+        for record in records.iter_mut().filter(|r| r.passenger_id % 2 == 0) {
+            record.sex = "female".to_string();
+        }
+        let mut config = SplitConfig::new().with_valid_ratio(0.2);
+        config.stratify_by("sex").unwrap();
+        assert!(config.stratify_by("cabin").is_err());
+
+        let (_, valid) = config.split(&records, 42);
+        let women = valid.iter().filter(|r| r.sex == "female").count();
+        assert_eq!((women, valid.len()), (10, 20));
+    }
+
+    #[test]
+    #[should_panic(expected = "valid ratio must be between 0 and 1")]
+    fn rejects_a_full_validation_split() {
+        SplitConfig::new()
+            .with_valid_ratio(1.0)
+            .split(&records(), 42);
+    }
+}
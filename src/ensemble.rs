@@ -0,0 +1,354 @@
+use std::{collections::HashMap, str::FromStr};
+
+use burn::{config::Config, tensor::backend::AutodiffBackend};
+
+use crate::{
+    calibration::{logit, sigmoid},
+    classifier::{self, Classifier},
+    cv::{cross_validate, CvConfig},
+    data::{RawData, TitanicRecord},
+    infer::write_submission,
+    metrics::Metrics,
+    model::{Prediction, DEFAULT_THRESHOLD},
+};
+
+/// How the survival probabilities of the models are combined.
+#[derive(Config, Debug, PartialEq)]
+pub enum Combiner {
+    Mean,
+    Weighted,
+    // A logistic regression on the log-odds of every model, fitted on out-of-fold predictions.
+    Stacked,
+}
+
+impl FromStr for Combiner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(Combiner::Mean),
+            "weighted" => Ok(Combiner::Weighted),
+            "stacked" => Ok(Combiner::Stacked),
+            _ => Err(format!(
+                "unknown combiner {}, expected mean, weighted or stacked",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Config, Debug)]
+pub struct EnsembleConfig {
+    #[config(default = "Combiner::Mean")]
+    pub combiner: Combiner,
+
+    // One weight per model for the weighted combiner, their validation accuracy when unset.
+    pub weights: Option<Vec<f64>>,
+
+    // Folds the out-of-fold predictions of the stacked combiner come from.
+    #[config(default = "CvConfig::new()")]
+    pub cv: CvConfig,
+
+    // L2 penalty on the meta-learner's coefficients, intercept excluded.
+    #[config(default = 1.0)]
+    pub meta_l2: f64,
+
+    #[config(default = 2000)]
+    pub meta_steps: usize,
+
+    #[config(default = 0.1)]
+    pub meta_learning_rate: f64,
+}
+
+/// Logistic regression over the log-odds of the base models.
+#[derive(Clone, Debug)]
+pub struct MetaLearner {
+    pub coefficients: Vec<f64>,
+    pub intercept: f64,
+}
+
+impl MetaLearner {
+    /// Full-batch gradient descent on the mean logistic loss. `probabilities` holds one column
+    /// per model.
+    pub fn fit(probabilities: &[Vec<f32>], labels: &[u8], config: &EnsembleConfig) -> MetaLearner {
+        let rows: Vec<Vec<f64>> = (0..labels.len())
+            .map(|i| {
+                probabilities
+                    .iter()
+                    .map(|column| logit(column[i]))
+                    .collect()
+            })
+            .collect();
+        let n = rows.len() as f64;
+        let mut meta = MetaLearner {
+            coefficients: vec![1.0 / probabilities.len() as f64; probabilities.len()],
+            intercept: 0.0,
+        };
+
+        for _ in 0..config.meta_steps {
+            let mut gradient = vec![0.0; meta.coefficients.len()];
+            let mut intercept_gradient = 0.0;
+            for (row, label) in rows.iter().zip(labels) {
+                let error = sigmoid(meta.logit(row)) - *label as f64;
+                for (g, x) in gradient.iter_mut().zip(row) {
+                    *g += error * x / n;
+                }
+                intercept_gradient += error / n;
+            }
+            for (w, g) in meta.coefficients.iter_mut().zip(gradient) {
+                *w -= config.meta_learning_rate * (g + config.meta_l2 * *w / n);
+            }
+            meta.intercept -= config.meta_learning_rate * intercept_gradient;
+        }
+        meta
+    }
+
+    fn logit(&self, row: &[f64]) -> f64 {
+        self.intercept
+            + self
+                .coefficients
+                .iter()
+                .zip(row)
+                .map(|(w, x)| w * x)
+                .sum::<f64>()
+    }
+}
+
+/// The fitted combination of the models' probabilities.
+pub enum Blend {
+    Weights(Vec<f64>),
+    Stacked(MetaLearner),
+}
+
+impl Blend {
+    /// Combines one column of probabilities per model into one per row.
+    pub fn combine(&self, probabilities: &[Vec<f32>]) -> Vec<f32> {
+        let rows = probabilities
+            .first()
+            .map(|column| column.len())
+            .unwrap_or(0);
+        (0..rows)
+            .map(|i| match self {
+                Blend::Weights(weights) => weights
+                    .iter()
+                    .zip(probabilities)
+                    .map(|(w, column)| w * column[i] as f64)
+                    .sum::<f64>() as f32,
+                Blend::Stacked(meta) => {
+                    let row: Vec<f64> = probabilities.iter().map(|c| logit(c[i])).collect();
+                    sigmoid(meta.logit(&row)) as f32
+                }
+            })
+            .collect()
+    }
+}
+
+/// Weights normalized to sum to one.
+fn normalize(weights: &[f64]) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    assert!(total > 0.0, "ensemble weights must sum to more than 0");
+    weights.iter().map(|w| w / total).collect()
+}
+
+/// Refits every model's config on the folds of `records`, returning one column of out-of-fold
+/// probabilities per model in the order of `records`, averaged over repeats.
+fn out_of_fold<B: AutodiffBackend>(
+    models: &[Box<dyn Classifier>],
+    records: &[TitanicRecord],
+    cv: &CvConfig,
+    device: &B::Device,
+) -> Vec<Vec<f32>> {
+    models
+        .iter()
+        .enumerate()
+        .map(|(i, model)| {
+            let result = cross_validate::<B>(records, cv, &model.config(), false, device);
+            let mut sums: HashMap<u32, (f32, usize)> = HashMap::new();
+            for prediction in result.oof {
+                let entry = sums.entry(prediction.id).or_insert((0.0, 0));
+                entry.0 += prediction.probability;
+                entry.1 += 1;
+            }
+            let column: Vec<f32> = records
+                .iter()
+                .map(|record| {
+                    let (sum, count) = sums[&record.passenger_id];
+                    sum / count as f32
+                })
+                .collect();
+            println!(
+                "[Stack - model {}] Out of fold {}",
+                i,
+                Metrics::new(&column, &labels(records), DEFAULT_THRESHOLD).summary()
+            );
+            column
+        })
+        .collect()
+}
+
+fn labels(records: &[TitanicRecord]) -> Vec<u8> {
+    records
+        .iter()
+        .map(|record| {
+            record
+                .survived
+                .expect("labeled data must have a Survived column")
+        })
+        .collect()
+}
+
+pub fn blend<B: AutodiffBackend>(
+    models: &[Box<dyn Classifier>],
+    config: &EnsembleConfig,
+    train_paths: &[String],
+    device: &B::Device,
+) -> Blend {
+    match config.combiner {
+        Combiner::Mean => Blend::Weights(vec![1.0 / models.len() as f64; models.len()]),
+        Combiner::Weighted => {
+            let weights = match &config.weights {
+                Some(weights) => {
+                    assert_eq!(weights.len(), models.len(), "expected one weight per model");
+                    weights.clone()
+                }
+                None => models
+                    .iter()
+                    .map(|model| model.metadata().valid_accuracy)
+                    .collect(),
+            };
+            Blend::Weights(normalize(&weights))
+        }
+        Combiner::Stacked => {
+            let raw_data = RawData::from_paths(train_paths);
+            let records = raw_data.get_all_rows();
+            let oof = out_of_fold::<B>(models, records, &config.cv, device);
+            Blend::Stacked(MetaLearner::fit(&oof, &labels(records), config))
+        }
+    }
+}
+
+/// Combines the saved models' probabilities for `input_path` and writes the submission,
+/// reporting every model and the ensemble on `valid_path` when given. Without a `threshold`
+/// every model is scored at its own tuned threshold and the ensemble at their mean.
+#[allow(clippy::too_many_arguments)]
+pub fn run<B: AutodiffBackend>(
+    model_paths: &[String],
+    config: &EnsembleConfig,
+    train_paths: &[String],
+    valid_path: Option<&str>,
+    input_path: &str,
+    output_path: &str,
+    threshold: Option<f32>,
+    probabilities: bool,
+    device: B::Device,
+) {
+    let models: Vec<Box<dyn Classifier>> = model_paths
+        .iter()
+        .map(|path| classifier::load::<B>(path, &device))
+        .collect();
+    let ensemble_threshold = threshold.unwrap_or_else(|| {
+        models.iter().map(|m| m.decision_threshold()).sum::<f32>() / models.len() as f32
+    });
+    for (path, model) in model_paths.iter().zip(&models) {
+        print!("{}: ", path);
+        model.describe();
+    }
+
+    let blend = blend::<B>(&models, config, train_paths, &device);
+    match &blend {
+        Blend::Weights(weights) => {
+            println!("  {:<32} {:>8}", "model", "weight");
+            for (path, weight) in model_paths.iter().zip(weights) {
+                println!("  {:<32} {:>8.3}", path, weight);
+            }
+        }
+        Blend::Stacked(meta) => {
+            println!("  {:<32} {:>11}", "model", "coefficient");
+            println!("  {:<32} {:>11.4}", "intercept", meta.intercept);
+            for (path, weight) in model_paths.iter().zip(&meta.coefficients) {
+                println!("  {:<32} {:>11.4}", path, weight);
+            }
+        }
+    }
+
+    if let Some(valid_path) = valid_path {
+        let records = RawData::new(valid_path).get_all_rows().clone();
+        let labels = labels(&records);
+        let columns: Vec<Vec<f32>> = models.iter().map(|m| m.predict_proba(&records)).collect();
+        for ((path, model), column) in model_paths.iter().zip(&models).zip(&columns) {
+            let threshold = threshold.unwrap_or_else(|| model.decision_threshold());
+            let metrics = Metrics::new(column, &labels, threshold);
+            println!(
+                "[Validate - {}] Loss {:.3} | Accuracy {:.3} %",
+                path,
+                metrics.log_loss,
+                metrics.accuracy * 100.0
+            );
+        }
+        let metrics = Metrics::new(&blend.combine(&columns), &labels, ensemble_threshold);
+        println!(
+            "*** [Validate - ensemble] Loss {:.3} | Accuracy {:.3} %",
+            metrics.log_loss,
+            metrics.accuracy * 100.0
+        );
+        println!("    {}", metrics.summary());
+    }
+
+    let records = RawData::new(input_path).get_all_rows().clone();
+    let columns: Vec<Vec<f32>> = models.iter().map(|m| m.predict_proba(&records)).collect();
+    let predictions: Vec<Prediction> = records
+        .iter()
+        .zip(blend.combine(&columns))
+        .map(|(record, probability)| Prediction {
+            id: record.passenger_id,
+            probability,
+            survived: (probability >= ensemble_threshold) as u8,
+        })
+        .collect();
+
+    write_submission(output_path, &predictions, probabilities);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_weights_to_sum_to_one() {
+        assert_eq!(normalize(&[1.0, 3.0]), vec![0.25, 0.75]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ensemble weights must sum to more than 0")]
+    fn rejects_zero_weights() {
+        normalize(&[0.0, 0.0]);
+    }
+
+    #[test]
+    fn weights_average_the_probabilities() {
+        let blend = Blend::Weights(vec![0.25, 0.75]);
+        let combined = blend.combine(&[vec![0.2, 1.0], vec![0.6, 0.0]]);
+        assert!((combined[0] - 0.5).abs() < 1e-6);
+        assert!((combined[1] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stacking_trusts_the_model_that_separates_the_labels() {
+        let labels = [1, 1, 0, 0, 1, 1, 0, 0];
+        let informative: Vec<f32> = labels
+            .iter()
+            .map(|&label| if label == 1 { 0.8 } else { 0.2 })
+            .collect();
+        let noise = vec![0.7, 0.3, 0.7, 0.3, 0.7, 0.3, 0.7, 0.3];
+        let probabilities = [informative, noise];
+        let meta = MetaLearner::fit(&probabilities, &labels, &EnsembleConfig::new());
+
+        assert!(meta.coefficients[0] > 1.0);
+        assert!(meta.coefficients[1].abs() < 0.05);
+        assert!(meta.intercept.abs() < 0.05);
+        let combined = Blend::Stacked(meta).combine(&probabilities);
+        for (probability, label) in combined.iter().zip(labels) {
+            assert_eq!((*probability >= 0.5) as u8, label);
+        }
+    }
+}
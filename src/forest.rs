@@ -0,0 +1,278 @@
+use burn::config::Config;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bundle::{bundle_path, create_parent, TrainingMetadata},
+    calibration::Calibrator,
+    classifier::{Classifier, ClassifierConfig, ModelKind, TrainingData},
+    data::{
+        FeatureConfig, ImputeConfig, Pipeline, PreprocessConfig, Scaler, SplitConfig, TitanicRecord,
+    },
+    tree::{grow, matrices, print_importance, validate, Matrices, MaxFeatures, Tree, TreeConfig},
+};
+
+/// Bagged CART trees averaging their leaf survival rates. A single tree without bootstrap is
+/// a plain decision tree.
+#[derive(Config, Debug)]
+pub struct ForestConfig {
+    #[config(default = 200)]
+    pub trees: usize,
+
+    // Fit every tree on rows drawn with replacement, leaving out-of-bag rows to score it on.
+    #[config(default = true)]
+    pub bootstrap: bool,
+
+    #[config(default = "TreeConfig::new().with_max_depth(8).with_max_features(MaxFeatures::Sqrt)")]
+    pub tree: TreeConfig,
+
+    #[config(default = 42)]
+    pub seed: u64,
+
+    #[config(default = "FeatureConfig::new()")]
+    pub features: FeatureConfig,
+
+    #[config(default = "ImputeConfig::new()")]
+    pub impute: ImputeConfig,
+
+    // Trees don't need scaling, and raw values keep the printed rules readable.
+    #[config(default = "PreprocessConfig::new().with_scaler(Scaler::None).with_log_fare(false)")]
+    pub preprocess: PreprocessConfig,
+
+    // Hold out a stratified validation split of the training data instead of reading a
+    // separate validation csv.
+    pub split: Option<SplitConfig>,
+}
+
+impl ForestConfig {
+    /// One shallow tree on all the rows and features, short enough to read as rules.
+    pub fn single_tree() -> ForestConfig {
+        ForestConfig::new()
+            .with_trees(1)
+            .with_bootstrap(false)
+            .with_tree(TreeConfig::new().with_max_depth(3))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Forest {
+    pub trees: Vec<Tree>,
+}
+
+impl Forest {
+    pub fn probability(&self, row: &[f32]) -> f32 {
+        self.trees.iter().map(|tree| tree.predict(row)).sum::<f32>() / self.trees.len() as f32
+    }
+
+    pub fn importance(&self, features: usize) -> Vec<f64> {
+        let mut importance = vec![0.0; features];
+        for tree in &self.trees {
+            tree.add_importance(&mut importance);
+        }
+        importance
+    }
+}
+
+/// Everything needed to predict with a trained forest or tree, saved to `<path>.json`.
+#[derive(Config)]
+pub struct ForestBundle {
+    #[config(default = "ModelKind::Forest")]
+    pub kind: ModelKind,
+    pub config: ForestConfig,
+    pub features: Vec<String>,
+    pub pipeline: Pipeline,
+    pub model: Forest,
+    pub metadata: TrainingMetadata,
+    // Accuracy in % of every training row predicted by the trees that didn't see it.
+    pub oob_accuracy: Option<f64>,
+    // Maps the model's probabilities to calibrated ones, fitted by `calibrate`.
+    pub calibration: Option<Calibrator>,
+
+    // Decision threshold tuned by `tune-threshold`, 0.5 when unset.
+    pub threshold: Option<f32>,
+}
+
+/// Grows the trees, returning the forest and its out-of-bag accuracy when bootstrapping.
+pub fn fit(
+    rows: &[Vec<f32>],
+    labels: &[f32],
+    config: &ForestConfig,
+    verbose: bool,
+) -> (Forest, Option<f64>) {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut oob_sums = vec![0.0; rows.len()];
+    let mut oob_votes = vec![0; rows.len()];
+    let mut trees = Vec::new();
+
+    for i in 0..config.trees.max(1) {
+        let sample: Vec<usize> = if config.bootstrap {
+            (0..rows.len())
+                .map(|_| rng.gen_range(0..rows.len()))
+                .collect()
+        } else {
+            (0..rows.len()).collect()
+        };
+
+        let mut in_bag = vec![false; rows.len()];
+        for &row in &sample {
+            in_bag[row] = true;
+        }
+        let tree = grow(rows, labels, sample, &config.tree, &mut rng);
+        for (row, _) in in_bag.iter().enumerate().filter(|(_, in_bag)| !**in_bag) {
+            oob_sums[row] += tree.predict(&rows[row]);
+            oob_votes[row] += 1;
+        }
+        trees.push(tree);
+
+        if verbose && (i + 1) % 50 == 0 {
+            println!("[Forest - Tree {}] {} nodes", i, trees[i].nodes.len());
+        }
+    }
+
+    let scored: Vec<bool> = (0..rows.len())
+        .filter(|&row| oob_votes[row] > 0)
+        .map(|row| (oob_sums[row] / oob_votes[row] as f32 >= 0.5) == (labels[row] >= 0.5))
+        .collect();
+    let oob_accuracy = if config.bootstrap && !scored.is_empty() {
+        Some(scored.iter().filter(|correct| **correct).count() as f64 / scored.len() as f64 * 100.0)
+    } else {
+        None
+    };
+
+    (Forest { trees }, oob_accuracy)
+}
+
+/// Fits the pipeline and the trees on `data`, validating on its validation records.
+pub fn fit_bundle(data: &TrainingData, config: &ForestConfig, verbose: bool) -> ForestBundle {
+    let Matrices {
+        pipeline,
+        rows,
+        labels,
+        valid_rows,
+        valid_labels,
+    } = matrices(data, &config.features, &config.impute, &config.preprocess);
+
+    let (model, oob_accuracy) = fit(&rows, &labels, config, verbose);
+    if let Some(accuracy) = oob_accuracy.filter(|_| verbose) {
+        println!("*** [Out of bag] Accuracy {:.3} %", accuracy);
+    }
+
+    let probabilities: Vec<f32> = valid_rows
+        .iter()
+        .map(|row| model.probability(row))
+        .collect();
+    let name = match model.trees.len() {
+        1 => "tree".to_string(),
+        trees => format!("{} trees", trees),
+    };
+    let metrics = validate(&name, &probabilities, &valid_labels, verbose);
+
+    let names = pipeline.names();
+    if verbose {
+        print_importance(&names, &model.importance(names.len()));
+    }
+
+    let metadata = TrainingMetadata::new(
+        data.train_path.clone(),
+        data.valid_path.clone(),
+        rows.len(),
+        model.trees.len(),
+        metrics.log_loss,
+        metrics.accuracy * 100.0,
+    );
+    ForestBundle::new(config.clone(), names, pipeline, model, metadata)
+        .with_oob_accuracy(oob_accuracy)
+}
+
+pub fn load(model_path: &str) -> ForestBundle {
+    ForestBundle::load(bundle_path(model_path))
+        .unwrap_or_else(|err| panic!("could not load model {}: {}", model_path, err))
+}
+
+/// Prints the first tree of a saved forest as if/else rules.
+pub fn rules(model_path: &str) {
+    let bundle = load(model_path);
+    if bundle.model.trees.len() > 1 {
+        println!("# tree 0 of {}", bundle.model.trees.len());
+    }
+    print!("{}", bundle.model.trees[0].rules(&bundle.features));
+}
+
+impl Classifier for ForestBundle {
+    fn kind(&self) -> ModelKind {
+        ModelKind::Forest
+    }
+
+    fn metadata(&self) -> &TrainingMetadata {
+        &self.metadata
+    }
+
+    fn config(&self) -> ClassifierConfig {
+        ClassifierConfig::Forest(self.config.clone())
+    }
+
+    fn calibration(&self) -> Option<&Calibrator> {
+        self.calibration.as_ref()
+    }
+
+    fn set_calibration(&mut self, calibrator: Option<Calibrator>) {
+        self.calibration = calibrator;
+    }
+
+    fn threshold(&self) -> Option<f32> {
+        self.threshold
+    }
+
+    fn set_threshold(&mut self, threshold: Option<f32>) {
+        self.threshold = threshold;
+    }
+
+    fn raw_proba(&self, records: &[TitanicRecord]) -> Vec<f32> {
+        records
+            .iter()
+            .map(|record| self.model.probability(&self.pipeline.transform(record)))
+            .collect()
+    }
+
+    fn save(&self, model_path: &str) {
+        create_parent(model_path);
+        Config::save(self, bundle_path(model_path)).expect("could not save model bundle");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Survival follows the first feature, the second one is noise.
+    fn data() -> (Vec<Vec<f32>>, Vec<f32>) {
+        let rows = (0..40).map(|x| vec![x as f32, (x % 3) as f32]).collect();
+        let labels = (0..40).map(|x| (x >= 20) as u8 as f32).collect();
+        (rows, labels)
+    }
+
+    #[test]
+    fn scores_bootstrapped_trees_out_of_bag() {
+        let (rows, labels) = data();
+        let config = ForestConfig::new()
+            .with_trees(25)
+            .with_tree(TreeConfig::new().with_max_features(MaxFeatures::All));
+        let (forest, oob_accuracy) = fit(&rows, &labels, &config, false);
+
+        assert_eq!(forest.trees.len(), 25);
+        let oob_accuracy = oob_accuracy.expect("bootstrapped trees leave rows out of bag");
+        assert!(oob_accuracy >= 90.0, "got {}", oob_accuracy);
+        assert!(forest.probability(&[5.0, 0.0]) < 0.5);
+        assert!(forest.probability(&[35.0, 0.0]) > 0.5);
+    }
+
+    #[test]
+    fn single_tree_has_no_out_of_bag_rows() {
+        let (rows, labels) = data();
+        let (forest, oob_accuracy) = fit(&rows, &labels, &ForestConfig::single_tree(), false);
+        assert_eq!(forest.trees.len(), 1);
+        assert_eq!(oob_accuracy, None);
+        assert_eq!(forest.probability(&[19.0, 1.0]), 0.0);
+        assert_eq!(forest.probability(&[20.0, 1.0]), 1.0);
+    }
+}
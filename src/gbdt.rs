@@ -0,0 +1,555 @@
+use burn::config::Config;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bundle::{bundle_path, create_parent, TrainingMetadata},
+    calibration::Calibrator,
+    classifier::{Classifier, ClassifierConfig, ModelKind, TrainingData},
+    data::{FeatureConfig, ImputeConfig, Pipeline, PreprocessConfig, SplitConfig, TitanicRecord},
+    metrics::Metrics,
+    model::DEFAULT_THRESHOLD,
+    tree::{matrices, print_importance, validate, Matrices, Node, Tree},
+};
+
+// Keeps leaf weights finite when a node's probabilities are all close to 0 or 1.
+const MIN_HESSIAN: f64 = 1e-6;
+
+/// Gradient-boosted trees on the logistic loss, grown leaf-wise on histograms of the features
+/// like LightGBM.
+#[derive(Config, Debug)]
+pub struct GbdtConfig {
+    // Maximum number of boosting rounds.
+    #[config(default = 300)]
+    pub trees: usize,
+
+    // Shrinkage applied to every tree's leaf values.
+    #[config(default = 0.05)]
+    pub learning_rate: f64,
+
+    #[config(default = 4)]
+    pub max_depth: usize,
+
+    #[config(default = 15)]
+    pub max_leaves: usize,
+
+    #[config(default = 5)]
+    pub min_samples_leaf: usize,
+
+    // L2 penalty on the leaf values.
+    #[config(default = 1.0)]
+    pub l2: f64,
+
+    // Smallest loss reduction worth a split.
+    #[config(default = 0.0)]
+    pub min_gain: f64,
+
+    // Histogram bins per feature, at most 256.
+    #[config(default = 64)]
+    pub bins: usize,
+
+    // Fraction of the rows each tree is fitted on.
+    #[config(default = 0.8)]
+    pub subsample: f64,
+
+    // Fraction of the features each tree may split on.
+    #[config(default = 1.0)]
+    pub colsample: f64,
+
+    // Stop once the validation loss hasn't improved for this many rounds and keep the best
+    // round's trees, 0 to always grow every tree.
+    #[config(default = 30)]
+    pub early_stopping_rounds: usize,
+
+    #[config(default = 42)]
+    pub seed: u64,
+
+    #[config(default = "FeatureConfig::new()")]
+    pub features: FeatureConfig,
+
+    #[config(default = "ImputeConfig::new()")]
+    pub impute: ImputeConfig,
+
+    #[config(default = "PreprocessConfig::new()")]
+    pub preprocess: PreprocessConfig,
+
+    // Hold out a stratified validation split of the training data instead of reading a
+    // separate validation csv.
+    pub split: Option<SplitConfig>,
+}
+
+/// The fitted ensemble: the log-odds of the training survival rate plus every tree's value.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Gbdt {
+    pub base_score: f32,
+    pub trees: Vec<Tree>,
+}
+
+impl Gbdt {
+    pub fn logit(&self, row: &[f32]) -> f32 {
+        self.base_score + self.trees.iter().map(|tree| tree.predict(row)).sum::<f32>()
+    }
+
+    pub fn probability(&self, row: &[f32]) -> f32 {
+        sigmoid(self.logit(row))
+    }
+
+    /// Total split gain of every feature over all trees.
+    pub fn importance(&self, features: usize) -> Vec<f64> {
+        let mut importance = vec![0.0; features];
+        for tree in &self.trees {
+            tree.add_importance(&mut importance);
+        }
+        importance
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Everything needed to predict with a trained ensemble, saved to `<path>.json`.
+#[derive(Config)]
+pub struct GbdtBundle {
+    #[config(default = "ModelKind::Gbdt")]
+    pub kind: ModelKind,
+    pub config: GbdtConfig,
+    pub features: Vec<String>,
+    pub pipeline: Pipeline,
+    pub model: Gbdt,
+    pub metadata: TrainingMetadata,
+    // Maps the model's probabilities to calibrated ones, fitted by `calibrate`.
+    pub calibration: Option<Calibrator>,
+
+    // Decision threshold tuned by `tune-threshold`, 0.5 when unset.
+    pub threshold: Option<f32>,
+}
+
+/// Quantile cut points of every feature. Bin `b` holds the values in
+/// `(cuts[b - 1], cuts[b]]`, so splitting after bin `b` sends `x <= cuts[b]` left.
+struct Histograms {
+    cuts: Vec<Vec<f32>>,
+    // Feature-major bin index of every row.
+    bins: Vec<Vec<u8>>,
+}
+
+impl Histograms {
+    fn new(rows: &[Vec<f32>], max_bins: usize) -> Histograms {
+        let max_bins = max_bins.clamp(2, 256);
+        let features = rows.first().map(|row| row.len()).unwrap_or(0);
+
+        let cuts: Vec<Vec<f32>> = (0..features)
+            .map(|j| {
+                let mut values: Vec<f32> = rows.iter().map(|row| row[j]).collect();
+                values.sort_by(|a, b| a.total_cmp(b));
+                let mut distinct = values.clone();
+                distinct.dedup();
+
+                // midpoints between distinct values when they fit, quantiles otherwise
+                let mut cuts: Vec<f32> = if distinct.len() <= max_bins {
+                    distinct.windows(2).map(|w| (w[0] + w[1]) / 2.0).collect()
+                } else {
+                    (1..max_bins)
+                        .map(|k| values[k * values.len() / max_bins])
+                        .collect()
+                };
+                cuts.dedup();
+                cuts
+            })
+            .collect();
+
+        let bins = cuts
+            .iter()
+            .enumerate()
+            .map(|(j, cuts)| {
+                rows.iter()
+                    .map(|row| cuts.partition_point(|cut| *cut < row[j]) as u8)
+                    .collect()
+            })
+            .collect();
+
+        Histograms { cuts, bins }
+    }
+}
+
+struct Split {
+    feature: usize,
+    bin: usize,
+    gain: f64,
+}
+
+/// A leaf that may still be split.
+struct Candidate {
+    node: usize,
+    rows: Vec<usize>,
+    depth: usize,
+    split: Option<Split>,
+}
+
+struct Grower<'a> {
+    config: &'a GbdtConfig,
+    histograms: &'a Histograms,
+    gradients: &'a [f64],
+    hessians: &'a [f64],
+    features: Vec<usize>,
+}
+
+impl Grower<'_> {
+    fn sums(&self, rows: &[usize]) -> (f64, f64) {
+        rows.iter().fold((0.0, 0.0), |(g, h), &i| {
+            (g + self.gradients[i], h + self.hessians[i])
+        })
+    }
+
+    fn score(&self, g: f64, h: f64) -> f64 {
+        g * g / (h + self.config.l2)
+    }
+
+    fn leaf(&self, rows: &[usize]) -> Node {
+        let (g, h) = self.sums(rows);
+        Node::Leaf {
+            value: (-g / (h + self.config.l2) * self.config.learning_rate) as f32,
+            samples: rows.len(),
+        }
+    }
+
+    fn best_split(&self, rows: &[usize]) -> Option<Split> {
+        let (total_g, total_h) = self.sums(rows);
+        let parent = self.score(total_g, total_h);
+        let min_samples = self.config.min_samples_leaf.max(1);
+        let mut best: Option<Split> = None;
+
+        for &feature in &self.features {
+            let n_bins = self.histograms.cuts[feature].len() + 1;
+            if n_bins < 2 {
+                continue;
+            }
+            let mut g = vec![0.0; n_bins];
+            let mut h = vec![0.0; n_bins];
+            let mut count = vec![0; n_bins];
+            for &i in rows {
+                let bin = self.histograms.bins[feature][i] as usize;
+                g[bin] += self.gradients[i];
+                h[bin] += self.hessians[i];
+                count[bin] += 1;
+            }
+
+            let (mut left_g, mut left_h, mut left_count) = (0.0, 0.0, 0);
+            for bin in 0..n_bins - 1 {
+                left_g += g[bin];
+                left_h += h[bin];
+                left_count += count[bin];
+                let right_count = rows.len() - left_count;
+                if left_count < min_samples || right_count < min_samples {
+                    continue;
+                }
+
+                let gain = (self.score(left_g, left_h)
+                    + self.score(total_g - left_g, total_h - left_h)
+                    - parent)
+                    / 2.0;
+                if gain > self.config.min_gain && best.as_ref().is_none_or(|b| gain > b.gain) {
+                    best = Some(Split { feature, bin, gain });
+                }
+            }
+        }
+        best
+    }
+
+    fn candidate(&self, node: usize, rows: Vec<usize>, depth: usize) -> Candidate {
+        let split = if depth < self.config.max_depth {
+            self.best_split(&rows)
+        } else {
+            None
+        };
+        Candidate {
+            node,
+            rows,
+            depth,
+            split,
+        }
+    }
+
+    /// Grows leaf-wise: the leaf with the largest gain is split next, until `max_leaves`.
+    fn grow(&self, rows: Vec<usize>) -> Tree {
+        let mut nodes = vec![self.leaf(&rows)];
+        let mut candidates = vec![self.candidate(0, rows, 0)];
+        let mut leaves = 1;
+
+        while leaves < self.config.max_leaves.max(2) {
+            let Some(best) = candidates
+                .iter()
+                .enumerate()
+                .filter_map(|(i, c)| c.split.as_ref().map(|s| (i, s.gain)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            let candidate = candidates.swap_remove(best);
+            let split = candidate.split.expect("candidate was picked for its split");
+
+            let bins = &self.histograms.bins[split.feature];
+            let (left_rows, right_rows): (Vec<usize>, Vec<usize>) = candidate
+                .rows
+                .iter()
+                .partition(|&&i| bins[i] as usize <= split.bin);
+
+            let (left, right) = (nodes.len(), nodes.len() + 1);
+            nodes.push(self.leaf(&left_rows));
+            nodes.push(self.leaf(&right_rows));
+            nodes[candidate.node] = Node::Split {
+                feature: split.feature,
+                threshold: self.histograms.cuts[split.feature][split.bin],
+                gain: split.gain as f32,
+                left,
+                right,
+            };
+            candidates.push(self.candidate(left, left_rows, candidate.depth + 1));
+            candidates.push(self.candidate(right, right_rows, candidate.depth + 1));
+            leaves += 1;
+        }
+
+        Tree { nodes }
+    }
+}
+
+/// Mean logistic loss of logits against 0/1 labels.
+fn log_loss(logits: &[f32], labels: &[f32]) -> f64 {
+    let probabilities: Vec<f32> = logits.iter().map(|x| sigmoid(*x)).collect();
+    let labels: Vec<u8> = labels.iter().map(|l| *l as u8).collect();
+    Metrics::new(&probabilities, &labels, DEFAULT_THRESHOLD).log_loss
+}
+
+/// Boosts trees on `rows`, stopping early on the validation loss. Returns the ensemble and
+/// the best round when early stopping cut it short.
+pub fn fit(
+    rows: &[Vec<f32>],
+    labels: &[f32],
+    valid_rows: &[Vec<f32>],
+    valid_labels: &[f32],
+    config: &GbdtConfig,
+    verbose: bool,
+) -> (Gbdt, Option<usize>) {
+    let histograms = Histograms::new(rows, config.bins);
+    let features = rows.first().map(|row| row.len()).unwrap_or(0);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    let rate = (labels.iter().sum::<f32>() / labels.len() as f32).clamp(1e-3, 1.0 - 1e-3);
+    let mut model = Gbdt {
+        base_score: (rate / (1.0 - rate)).ln(),
+        trees: Vec::new(),
+    };
+    let mut logits = vec![model.base_score; rows.len()];
+    let mut valid_logits = vec![model.base_score; valid_rows.len()];
+    let mut best: Option<(usize, f64)> = None;
+
+    for round in 0..config.trees {
+        let mut gradients = vec![0.0; rows.len()];
+        let mut hessians = vec![0.0; rows.len()];
+        for i in 0..rows.len() {
+            let p = sigmoid(logits[i]) as f64;
+            gradients[i] = p - labels[i] as f64;
+            hessians[i] = (p * (1.0 - p)).max(MIN_HESSIAN);
+        }
+
+        let mut sample: Vec<usize> = (0..rows.len()).collect();
+        sample.shuffle(&mut rng);
+        sample.truncate(((rows.len() as f64 * config.subsample).ceil() as usize).max(1));
+        let mut columns: Vec<usize> = (0..features).collect();
+        columns.shuffle(&mut rng);
+        columns.truncate(((features as f64 * config.colsample).ceil() as usize).max(1));
+        columns.sort();
+
+        let grower = Grower {
+            config,
+            histograms: &histograms,
+            gradients: &gradients,
+            hessians: &hessians,
+            features: columns,
+        };
+        let tree = grower.grow(sample);
+        for (logit, row) in logits.iter_mut().zip(rows) {
+            *logit += tree.predict(row);
+        }
+        for (logit, row) in valid_logits.iter_mut().zip(valid_rows) {
+            *logit += tree.predict(row);
+        }
+        model.trees.push(tree);
+
+        let valid_loss = log_loss(&valid_logits, valid_labels);
+        if verbose && (round + 1) % 10 == 0 {
+            println!(
+                "[Boost - Round {}] Loss {:.3} | Valid Loss {:.3}",
+                round,
+                log_loss(&logits, labels),
+                valid_loss
+            );
+        }
+
+        // without validation rows the loss is always 0 and would stop on the first round
+        if config.early_stopping_rounds == 0 || valid_rows.is_empty() {
+            continue;
+        }
+        match best {
+            Some((_, loss)) if valid_loss >= loss => {}
+            _ => best = Some((round, valid_loss)),
+        }
+        if let Some((best_round, _)) = best {
+            if round - best_round >= config.early_stopping_rounds {
+                if verbose {
+                    println!(
+                        "Early stopping after round {}, no improvement since round {}",
+                        round, best_round
+                    );
+                }
+                break;
+            }
+        }
+    }
+
+    let best_round = best.map(|(round, _)| round);
+    if let Some(round) = best_round {
+        model.trees.truncate(round + 1);
+    }
+    (model, best_round)
+}
+
+/// Fits the pipeline and the ensemble on `data`, validating on its validation records.
+pub fn fit_bundle(data: &TrainingData, config: &GbdtConfig, verbose: bool) -> GbdtBundle {
+    let Matrices {
+        pipeline,
+        rows,
+        labels,
+        valid_rows,
+        valid_labels,
+    } = matrices(data, &config.features, &config.impute, &config.preprocess);
+
+    let (model, best_round) = fit(&rows, &labels, &valid_rows, &valid_labels, config, verbose);
+
+    let probabilities: Vec<f32> = valid_rows
+        .iter()
+        .map(|row| model.probability(row))
+        .collect();
+    let metrics = validate(
+        &format!("{} trees", model.trees.len()),
+        &probabilities,
+        &valid_labels,
+        verbose,
+    );
+
+    let names = pipeline.names();
+    if verbose {
+        print_importance(&names, &model.importance(names.len()));
+    }
+
+    let metadata = TrainingMetadata::new(
+        data.train_path.clone(),
+        data.valid_path.clone(),
+        rows.len(),
+        model.trees.len(),
+        metrics.log_loss,
+        metrics.accuracy * 100.0,
+    )
+    .with_best_epoch(best_round);
+    GbdtBundle::new(config.clone(), names, pipeline, model, metadata)
+}
+
+pub fn load(model_path: &str) -> GbdtBundle {
+    GbdtBundle::load(bundle_path(model_path))
+        .unwrap_or_else(|err| panic!("could not load model {}: {}", model_path, err))
+}
+
+impl Classifier for GbdtBundle {
+    fn kind(&self) -> ModelKind {
+        ModelKind::Gbdt
+    }
+
+    fn metadata(&self) -> &TrainingMetadata {
+        &self.metadata
+    }
+
+    fn config(&self) -> ClassifierConfig {
+        ClassifierConfig::Gbdt(self.config.clone())
+    }
+
+    fn calibration(&self) -> Option<&Calibrator> {
+        self.calibration.as_ref()
+    }
+
+    fn set_calibration(&mut self, calibrator: Option<Calibrator>) {
+        self.calibration = calibrator;
+    }
+
+    fn threshold(&self) -> Option<f32> {
+        self.threshold
+    }
+
+    fn set_threshold(&mut self, threshold: Option<f32>) {
+        self.threshold = threshold;
+    }
+
+    fn raw_proba(&self, records: &[TitanicRecord]) -> Vec<f32> {
+        records
+            .iter()
+            .map(|record| self.model.probability(&self.pipeline.transform(record)))
+            .collect()
+    }
+
+    fn save(&self, model_path: &str) {
+        create_parent(model_path);
+        Config::save(self, bundle_path(model_path)).expect("could not save model bundle");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_cut_between_distinct_values() {
+        let rows: Vec<Vec<f32>> = [1.0, 2.0, 2.0, 4.0].iter().map(|x| vec![*x]).collect();
+        let histograms = Histograms::new(&rows, 64);
+        assert_eq!(histograms.cuts, vec![vec![1.5, 3.0]]);
+        assert_eq!(histograms.bins, vec![vec![0, 1, 1, 2]]);
+    }
+
+    #[test]
+    fn histograms_fall_back_to_quantiles() {
+        let rows: Vec<Vec<f32>> = (0..10).map(|x| vec![x as f32]).collect();
+        let histograms = Histograms::new(&rows, 2);
+        assert_eq!(histograms.cuts, vec![vec![5.0]]);
+        assert_eq!(histograms.bins, vec![vec![0, 0, 0, 0, 0, 0, 1, 1, 1, 1]]);
+    }
+
+    #[test]
+    fn grows_every_tree_without_validation_rows() {
+        let rows: Vec<Vec<f32>> = (0..20).map(|x| vec![x as f32]).collect();
+        let labels: Vec<f32> = (0..20).map(|x| (x >= 10) as u8 as f32).collect();
+        let config = GbdtConfig::new()
+            .with_trees(20)
+            .with_early_stopping_rounds(5);
+
+        let (model, best_round) = fit(&rows, &labels, &[], &[], &config, false);
+        assert_eq!(best_round, None);
+        assert_eq!(model.trees.len(), 20);
+    }
+
+    #[test]
+    fn boosting_separates_the_classes() {
+        let rows: Vec<Vec<f32>> = (0..40).map(|x| vec![x as f32, (x % 3) as f32]).collect();
+        let labels: Vec<f32> = (0..40).map(|x| (x >= 20) as u8 as f32).collect();
+        let config = GbdtConfig::new()
+            .with_trees(50)
+            .with_learning_rate(0.3)
+            .with_subsample(1.0)
+            .with_early_stopping_rounds(0);
+
+        let (model, best_round) = fit(&rows, &labels, &rows, &labels, &config, false);
+        assert_eq!(best_round, None);
+        assert_eq!(model.trees.len(), 50);
+        assert!(model.probability(&[5.0, 0.0]) < 0.1);
+        assert!(model.probability(&[35.0, 0.0]) > 0.9);
+        assert!(model.importance(2)[0] > model.importance(2)[1]);
+    }
+}
@@ -1,33 +1,129 @@
-#![allow(dead_code)]
-use burn::{
-    module::Module,
-    prelude::Backend,
-    record::{FullPrecisionSettings, NamedMpkFileRecorder, Recorder},
-};
+use burn::prelude::Backend;
 
 use crate::{
-    data,
-    model::{ModelConfig, ModelRecord},
+    bundle::ModelBundle,
+    classifier,
+    data::RawData,
+    metrics::Metrics,
+    model::{Model, Prediction},
 };
 
-pub fn infer<B: Backend>(model_path: &str, device: &B::Device) {
-    let record: ModelRecord<B> = NamedMpkFileRecorder::<FullPrecisionSettings>::new()
-        .load(model_path.into(), device)
-        .expect("could not load model");
-    let model = ModelConfig::new().init(device).load_record(record);
+pub fn load_model<B: Backend>(model_path: &str, device: &B::Device) -> (ModelBundle, Model<B>) {
+    ModelBundle::load_model(model_path, device)
+        .unwrap_or_else(|err| panic!("could not load model {}: {}", model_path, err))
+}
 
-    let data = data::TestDataSet::<B>::new(device);
-    let batch = data.batch();
-    let output = model.interface(&batch);
+/// Predicts with a saved model of any kind, at its tuned threshold unless `threshold` is given.
+pub fn infer<B: Backend>(
+    model_path: &str,
+    input_path: &str,
+    output_path: &str,
+    threshold: Option<f32>,
+    probabilities: bool,
+    device: &B::Device,
+) {
+    let model = classifier::load::<B>(model_path, device);
+    let threshold = threshold.unwrap_or_else(|| model.decision_threshold());
+    let predictions = model.predict(RawData::new(input_path).get_all_rows(), threshold);
 
-    let file = std::fs::File::create("data/submission.csv").expect("could not create file");
+    write_submission(output_path, &predictions, probabilities);
+}
+
+/// Writes `PassengerId,Survived`, plus `Probability` when asked, the Kaggle submission format.
+pub fn write_submission(output_path: &str, predictions: &[Prediction], probabilities: bool) {
+    let file = std::fs::File::create(output_path).expect("could not create file");
 
     let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(&["PassengerId", "Survived"])
-        .expect("could not write header");
+    if probabilities {
+        wtr.write_record(["PassengerId", "Survived", "Probability"])
+            .expect("could not write header");
+    } else {
+        wtr.write_record(["PassengerId", "Survived"])
+            .expect("could not write header");
+    }
+
+    for item in predictions {
+        let id = item.id.to_string();
+        let survived = item.survived.to_string();
+        if probabilities {
+            wtr.write_record([&id, &survived, &format!("{:.6}", item.probability)])
+                .expect("could not write record");
+        } else {
+            wtr.write_record([&id, &survived])
+                .expect("could not write record");
+        }
+    }
+}
+
+pub fn evaluate<B: Backend>(
+    model_path: &str,
+    input_path: &str,
+    threshold: Option<f32>,
+    report_path: Option<&str>,
+    device: &B::Device,
+) {
+    let model = classifier::load::<B>(model_path, device);
+    model.describe();
+    let threshold = threshold.unwrap_or_else(|| model.decision_threshold());
+
+    let records = RawData::new(input_path).get_all_rows().clone();
+    let probabilities = model.predict_proba(&records);
+    let labels: Vec<u8> = records
+        .iter()
+        .map(|record| {
+            record
+                .survived
+                .expect("evaluation data must have a Survived column")
+        })
+        .collect();
+    let metrics = Metrics::new(&probabilities, &labels, threshold);
+
+    println!(
+        "[Evaluate - {}] Loss {:.3} | Accuracy {:.3} %",
+        input_path,
+        metrics.log_loss,
+        metrics.accuracy * 100.0,
+    );
+    metrics.print();
+    if let Some(path) = report_path {
+        metrics.save(path);
+    }
+}
+
+/// Prints the coefficients of a logistic regression bundle, largest effect first. They apply
+/// to the features after preprocessing, so scaled features compare by one standard deviation
+/// (or their min-max range).
+pub fn coefficients<B: Backend>(model_path: &str, device: &B::Device) {
+    let (bundle, model): (_, Model<B>) = load_model(model_path, device);
+    if !print_coefficients(&bundle, &model) {
+        panic!(
+            "model {} has hidden layers, only logistic regression has coefficients",
+            model_path
+        );
+    }
+}
+
+/// False when the model has hidden layers.
+pub fn print_coefficients<B: Backend>(bundle: &ModelBundle, model: &Model<B>) -> bool {
+    let Some((weights, bias)) = model.coefficients() else {
+        return false;
+    };
+
+    let mut named: Vec<(String, f32)> = bundle
+        .model
+        .input_names(&bundle.features)
+        .into_iter()
+        .zip(weights)
+        .collect();
+    named.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
 
-    for item in output {
-        wtr.write_record(&[&item.0.to_string(), &item.1.to_string()])
-            .expect("could not write record");
+    println!(
+        "  {:<24} {:>11}  {:>10}",
+        "feature", "coefficient", "odds ratio"
+    );
+    println!("  {:<24} {:>11.4}", "intercept", bias);
+    for (name, weight) in named {
+        println!("  {:<24} {:>11.4}  {:>10.3}", name, weight, weight.exp());
     }
+    true
 }
@@ -0,0 +1,283 @@
+use std::{path::Path, sync::Arc};
+
+use burn::{
+    config::Config,
+    data::dataloader::{DataLoader, DataLoaderBuilder},
+    optim::{GradientsParams, Optimizer},
+    tensor::{backend::AutodiffBackend, ElementConversion},
+};
+use plotly::{
+    common::Mode,
+    layout::{Axis, AxisType},
+    Layout, Plot, Scatter,
+};
+
+use crate::{
+    data::{Batch, DataSet, RawData, TitanicBatcher},
+    model::{Model, ModelConfig, DEFAULT_THRESHOLD},
+    optimizer::OptimizerKind,
+    training::{fit_pipeline, ExpConfig},
+};
+
+/// Learning rate range test: the rate grows exponentially from `start_lr` to `end_lr` over
+/// `steps` optimizer steps while the training loss is recorded.
+#[derive(Config, Debug)]
+pub struct LrFindConfig {
+    #[config(default = 1e-7)]
+    pub start_lr: f64,
+
+    #[config(default = 1.0)]
+    pub end_lr: f64,
+
+    #[config(default = 200)]
+    pub steps: usize,
+
+    // Weight of the exponential moving average applied to the batch losses.
+    #[config(default = 0.98)]
+    pub smoothing: f64,
+
+    // Stop once the smoothed loss exceeds the best one by this factor.
+    #[config(default = 4.0)]
+    pub divergence: f64,
+}
+
+impl LrFindConfig {
+    pub fn lr(&self, step: usize) -> f64 {
+        let t = step as f64 / (self.steps.max(2) - 1) as f64;
+        self.start_lr * (self.end_lr / self.start_lr).powf(t)
+    }
+}
+
+const SLOPE_WINDOW: usize = 5;
+
+/// Smoothed training loss at every learning rate tried.
+pub struct LrCurve {
+    pub learning_rates: Vec<f64>,
+    pub losses: Vec<f64>,
+}
+
+impl LrCurve {
+    /// Rate where the loss falls fastest against log10 of the rate, looking only at the part of
+    /// the curve before its minimum. Slopes span `SLOPE_WINDOW` steps on each side, single steps
+    /// being too noisy.
+    pub fn suggestion(&self) -> Option<f64> {
+        let lowest = self
+            .losses
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))?
+            .0;
+
+        let last = self.losses.len() - 1;
+        (1..=lowest)
+            .map(|i| {
+                let (a, b) = (i.saturating_sub(SLOPE_WINDOW), (i + SLOPE_WINDOW).min(last));
+                let slope = (self.losses[b] - self.losses[a])
+                    / (self.learning_rates[b].log10() - self.learning_rates[a].log10());
+                (i, slope)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| self.learning_rates[i])
+    }
+
+    pub fn plot(&self, output_path: &Path) {
+        let trace = Scatter::new(self.learning_rates.clone(), self.losses.clone())
+            .name("Smoothed loss")
+            .mode(Mode::Lines);
+
+        let mut plot = Plot::new();
+        plot.add_trace(trace);
+
+        let title = match self.suggestion() {
+            Some(lr) => {
+                let i = self
+                    .learning_rates
+                    .iter()
+                    .position(|r| *r == lr)
+                    .expect("suggestion should be on the curve");
+                let marker = Scatter::new(vec![lr], vec![self.losses[i]])
+                    .name("Steepest descent")
+                    .mode(Mode::Markers);
+                plot.add_trace(marker);
+                format!("Learning rate finder: suggested {:.2e}", lr)
+            }
+            None => "Learning rate finder".to_string(),
+        };
+
+        let layout = Layout::new()
+            .title(title.as_str())
+            .x_axis(Axis::new().title("Learning Rate").type_(AxisType::Log))
+            .y_axis(Axis::new().title("Loss"));
+        plot.set_layout(layout);
+
+        plot.write_html(output_path);
+    }
+}
+
+/// Trains a throwaway model on `train_paths` with the configured optimizer and features,
+/// ignoring the experiment's learning rate and schedule.
+pub fn run<B: AutodiffBackend>(
+    train_paths: &[String],
+    config: ExpConfig,
+    mut model_config: ModelConfig,
+    lr_find: &LrFindConfig,
+    output_path: &str,
+    device: B::Device,
+) {
+    let raw_data = RawData::from_paths(train_paths);
+    let records = raw_data.get_all_rows();
+    let pipeline = fit_pipeline(records, &config, &mut model_config);
+
+    B::seed(config.seed);
+    let model: Model<B> = model_config.init(&device);
+
+    let dataloader = DataLoaderBuilder::new(TitanicBatcher::<B>::new(device.clone()))
+        .batch_size(config.batch_size)
+        .shuffle(config.seed)
+        .num_workers(config.workers)
+        .build(DataSet::from_records(records, &pipeline));
+
+    let optimizer = &config.optimizer;
+    let curve = match optimizer.kind {
+        OptimizerKind::Adam => sweep(
+            model,
+            optimizer.adam().init(),
+            &dataloader,
+            lr_find,
+            &device,
+        ),
+        OptimizerKind::AdamW => sweep(
+            model,
+            optimizer.adamw().init(),
+            &dataloader,
+            lr_find,
+            &device,
+        ),
+        OptimizerKind::Sgd => sweep(model, optimizer.sgd().init(), &dataloader, lr_find, &device),
+        OptimizerKind::RmsProp => sweep(
+            model,
+            optimizer.rmsprop().init(),
+            &dataloader,
+            lr_find,
+            &device,
+        ),
+    };
+
+    println!(
+        "*** [LR find - {} steps] {:.2e} to {:.2e}",
+        curve.learning_rates.len(),
+        lr_find.start_lr,
+        curve
+            .learning_rates
+            .last()
+            .copied()
+            .unwrap_or(lr_find.start_lr)
+    );
+    match curve.suggestion() {
+        Some(lr) => println!("Suggested learning rate: {:.2e}", lr),
+        None => println!("No suggestion, the loss never decreased"),
+    }
+
+    curve.plot(Path::new(output_path));
+}
+
+fn sweep<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+    mut model: Model<B>,
+    mut optim: O,
+    dataloader: &Arc<dyn DataLoader<Batch<B>>>,
+    lr_find: &LrFindConfig,
+    device: &B::Device,
+) -> LrCurve {
+    let mut curve = LrCurve {
+        learning_rates: Vec::new(),
+        losses: Vec::new(),
+    };
+    let mut average = 0.0;
+    let mut best = f64::INFINITY;
+
+    // cycles through the epochs until enough steps were taken
+    let mut step = 0;
+    while step < lr_find.steps {
+        for batch in dataloader.iter() {
+            if step == lr_find.steps {
+                break;
+            }
+            let lr = lr_find.lr(step);
+            let output = model.forward_step(&batch, DEFAULT_THRESHOLD, device);
+            let loss = output.loss.clone().into_scalar().elem::<f64>();
+
+            // bias-corrected moving average
+            average = lr_find.smoothing * average + (1.0 - lr_find.smoothing) * loss;
+            let smoothed = average / (1.0 - lr_find.smoothing.powi(step as i32 + 1));
+            // a NaN or infinite loss has no place on the curve
+            if !smoothed.is_finite() {
+                return curve;
+            }
+            curve.learning_rates.push(lr);
+            curve.losses.push(smoothed);
+
+            if smoothed > lr_find.divergence * best {
+                return curve;
+            }
+            best = best.min(smoothed);
+
+            let grads = output.loss.backward();
+            let grads = GradientsParams::from_grads(grads, &model);
+            model = optim.step(lr, model, grads);
+            step += 1;
+        }
+    }
+    curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_grow_exponentially() {
+        let config = LrFindConfig::new()
+            .with_start_lr(1e-6)
+            .with_end_lr(1e-2)
+            .with_steps(5);
+        assert!((config.lr(0) - 1e-6).abs() < 1e-15);
+        assert!((config.lr(2) - 1e-4).abs() < 1e-12);
+        assert!((config.lr(4) - 1e-2).abs() < 1e-10);
+    }
+
+    #[test]
+    fn suggests_the_steepest_descent_before_the_minimum() {
+        // falls fastest at 1e-3, bottoms out at 10^-1.5 and blows up after that
+        let exponents: Vec<f64> = (0..=50).map(|k| -6.0 + k as f64 * 0.1).collect();
+        let curve = LrCurve {
+            learning_rates: exponents.iter().map(|e| 10f64.powf(*e)).collect(),
+            losses: exponents
+                .iter()
+                .map(|e| {
+                    let loss = 0.7 - 0.5 * (2.0 * (e + 3.0)).tanh();
+                    if *e > -1.5 {
+                        loss + 2.0 * (e + 1.5)
+                    } else {
+                        loss
+                    }
+                })
+                .collect(),
+        };
+
+        let suggestion = curve.suggestion().expect("the curve has points");
+        assert!(
+            (suggestion.log10() + 3.0).abs() < 0.05,
+            "got {}",
+            suggestion
+        );
+    }
+
+    #[test]
+    fn no_suggestion_without_points() {
+        let curve = LrCurve {
+            learning_rates: Vec::new(),
+            losses: Vec::new(),
+        };
+        assert_eq!(curve.suggestion(), None);
+    }
+}
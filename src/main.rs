@@ -1,9 +1,29 @@
+mod bundle;
+mod calibration;
+mod checkpoint;
+mod classifier;
+mod cli;
+mod cv;
 mod data;
+mod ensemble;
+mod forest;
+mod gbdt;
 mod infer;
+mod lr_find;
+mod metrics;
 mod model;
+mod optimizer;
+mod scheduler;
+mod search;
+mod threshold;
 mod training;
+mod tree;
 
 use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+use clap::Parser;
+
+use classifier::ClassifierConfig;
+use cli::{Cli, Command};
 
 pub type MyDevice = Wgpu<f32, i32>;
 pub type MyBackend = Autodiff<MyDevice>;
@@ -12,10 +32,139 @@ pub fn get_device() -> WgpuDevice {
     WgpuDevice::default()
 }
 
-const MODEL_PATH: &str = "model/bce-adam";
-
 fn main() {
+    let cli = Cli::parse();
     let device = get_device();
-    // training::train::<MyBackend>(MODEL_PATH, device);
-    infer::infer::<MyBackend>(MODEL_PATH, &device);
+
+    match cli.command {
+        Command::Train(args) => {
+            let config = args.exp.config();
+            let model_config = args.model_config.config();
+            training::train::<MyBackend>(
+                &args.model,
+                &args.train,
+                &args.valid,
+                config,
+                model_config,
+                args.resume,
+                device,
+            );
+        }
+        Command::Infer(args) => {
+            infer::infer::<MyBackend>(
+                &args.model,
+                &args.input,
+                &args.output,
+                args.threshold,
+                args.probabilities,
+                &device,
+            );
+        }
+        Command::TrainGbdt(args) => {
+            let config = ClassifierConfig::Gbdt(args.config());
+            classifier::train::<MyBackend>(&args.model, &args.train, &args.valid, &config, &device);
+        }
+        Command::TrainTree(args) => {
+            let config = ClassifierConfig::Forest(args.config());
+            classifier::train::<MyBackend>(&args.model, &args.train, &args.valid, &config, &device);
+            forest::rules(&args.model);
+        }
+        Command::TrainForest(args) => {
+            let config = ClassifierConfig::Forest(args.config());
+            classifier::train::<MyBackend>(&args.model, &args.train, &args.valid, &config, &device);
+        }
+        Command::Rules(args) => {
+            forest::rules(&args.model);
+        }
+        Command::Ensemble(args) => {
+            ensemble::run::<MyBackend>(
+                &args.models,
+                &args.config(),
+                &args.train,
+                args.valid.as_deref(),
+                &args.input,
+                &args.output,
+                args.threshold,
+                args.probabilities,
+                device,
+            );
+        }
+        Command::Evaluate(args) => {
+            infer::evaluate::<MyBackend>(
+                &args.model,
+                &args.input,
+                args.threshold,
+                args.report.as_deref(),
+                &device,
+            );
+        }
+        Command::TuneThreshold(args) => {
+            threshold::run::<MyBackend>(
+                &args.model,
+                &args.objective,
+                args.valid.as_deref(),
+                device,
+            );
+        }
+        Command::Calibrate(args) => {
+            calibration::run::<MyBackend>(
+                &args.model,
+                &args.method,
+                args.valid.as_deref(),
+                args.bins,
+                &args.output,
+                device,
+            );
+        }
+        Command::Coefficients(args) => {
+            infer::coefficients::<MyBackend>(&args.model, &device);
+        }
+        Command::Explore(args) => {
+            data::Visualizer::new(&args.input).render(&args.output_dir);
+        }
+        Command::Cv(args) => {
+            cv::run::<MyBackend>(
+                &args.train,
+                &args.config(),
+                &args.classifier(),
+                args.oof.as_deref(),
+                device,
+            );
+        }
+        Command::LrFind(args) => {
+            let config = args.exp.config();
+            let model_config = args.model_config.config();
+            lr_find::run::<MyBackend>(
+                &args.train,
+                config,
+                model_config,
+                &args.config(),
+                &args.output,
+                device,
+            );
+        }
+        Command::Search(args) => {
+            let config = args.exp.config();
+            let model_config = args.model_config.config();
+            search::run::<MyBackend>(
+                &args.train,
+                &search::SearchSpace::load(&args.space),
+                &args.config(),
+                config,
+                model_config,
+                &args.results,
+                &args.output,
+                device,
+            );
+        }
+        Command::Split(args) => {
+            data::split_files(
+                &args.input,
+                &args.train_output,
+                &args.valid_output,
+                &args.config(),
+                args.seed,
+            );
+        }
+    }
 }
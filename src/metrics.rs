@@ -0,0 +1,361 @@
+use serde::Serialize;
+
+// Keeps log-loss finite for probabilities of exactly 0 or 1.
+const EPSILON: f64 = 1e-7;
+
+// Equal-width probability bins of the expected calibration error.
+pub const CALIBRATION_BINS: usize = 10;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ConfusionMatrix {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub true_negatives: usize,
+    pub false_negatives: usize,
+}
+
+impl ConfusionMatrix {
+    pub fn new(probabilities: &[f32], labels: &[u8], threshold: f32) -> ConfusionMatrix {
+        let mut matrix = ConfusionMatrix::default();
+        for (probability, label) in probabilities.iter().zip(labels) {
+            match (*probability >= threshold, *label == 1) {
+                (true, true) => matrix.true_positives += 1,
+                (true, false) => matrix.false_positives += 1,
+                (false, false) => matrix.true_negatives += 1,
+                (false, true) => matrix.false_negatives += 1,
+            }
+        }
+        matrix
+    }
+}
+
+/// `a / b`, or 0 when nothing was counted.
+fn ratio(a: f64, b: f64) -> f64 {
+    if b == 0.0 {
+        0.0
+    } else {
+        a / b
+    }
+}
+
+/// Classification metrics of survival probabilities against 0/1 labels, survived being the
+/// positive class. Rates are fractions between 0 and 1, MCC goes from -1 to 1.
+#[derive(Clone, Debug, Serialize)]
+pub struct Metrics {
+    pub rows: usize,
+    pub threshold: f32,
+    pub confusion: ConfusionMatrix,
+    pub accuracy: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub specificity: f64,
+    pub balanced_accuracy: f64,
+    pub mcc: f64,
+    pub log_loss: f64,
+    pub brier: f64,
+    // Expected calibration error over `CALIBRATION_BINS` bins.
+    pub ece: f64,
+    pub roc_auc: f64,
+    pub pr_auc: f64,
+}
+
+impl Metrics {
+    pub fn new(probabilities: &[f32], labels: &[u8], threshold: f32) -> Metrics {
+        let confusion = ConfusionMatrix::new(probabilities, labels, threshold);
+        let tp = confusion.true_positives as f64;
+        let fp = confusion.false_positives as f64;
+        let tn = confusion.true_negatives as f64;
+        let fn_ = confusion.false_negatives as f64;
+        let rows = probabilities.len();
+
+        let precision = ratio(tp, tp + fp);
+        let recall = ratio(tp, tp + fn_);
+        let specificity = ratio(tn, tn + fp);
+        let mcc = ratio(
+            tp * tn - fp * fn_,
+            ((tp + fp) * (tp + fn_) * (tn + fp) * (tn + fn_)).sqrt(),
+        );
+
+        let mut log_loss = 0.0;
+        let mut brier = 0.0;
+        for (probability, label) in probabilities.iter().zip(labels) {
+            let p = (*probability as f64).clamp(EPSILON, 1.0 - EPSILON);
+            let y = *label as f64;
+            log_loss -= y * p.ln() + (1.0 - y) * (1.0 - p).ln();
+            brier += (*probability as f64 - y).powi(2);
+        }
+
+        Metrics {
+            rows,
+            threshold,
+            accuracy: ratio(tp + tn, rows as f64),
+            precision,
+            recall,
+            f1: ratio(2.0 * precision * recall, precision + recall),
+            specificity,
+            balanced_accuracy: (recall + specificity) / 2.0,
+            mcc,
+            log_loss: ratio(log_loss, rows as f64),
+            brier: ratio(brier, rows as f64),
+            ece: expected_calibration_error(probabilities, labels, CALIBRATION_BINS),
+            roc_auc: roc_auc(probabilities, labels),
+            pr_auc: pr_auc(probabilities, labels),
+            confusion,
+        }
+    }
+
+    /// `(name, value)` pairs in report order, used for the csv report.
+    pub fn values(&self) -> Vec<(&'static str, f64)> {
+        let mut values = vec![
+            ("rows", self.rows as f64),
+            ("threshold", self.threshold as f64),
+            ("true_positives", self.confusion.true_positives as f64),
+            ("false_positives", self.confusion.false_positives as f64),
+            ("true_negatives", self.confusion.true_negatives as f64),
+            ("false_negatives", self.confusion.false_negatives as f64),
+        ];
+        values.extend(self.scores());
+        values
+    }
+
+    /// The rates, losses and areas, everything but the counts.
+    pub fn scores(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("accuracy", self.accuracy),
+            ("precision", self.precision),
+            ("recall", self.recall),
+            ("f1", self.f1),
+            ("specificity", self.specificity),
+            ("balanced_accuracy", self.balanced_accuracy),
+            ("mcc", self.mcc),
+            ("log_loss", self.log_loss),
+            ("brier", self.brier),
+            ("ece", self.ece),
+            ("roc_auc", self.roc_auc),
+            ("pr_auc", self.pr_auc),
+        ]
+    }
+
+    /// One line for the per-epoch training log.
+    pub fn summary(&self) -> String {
+        format!(
+            "Precision {:.3} | Recall {:.3} | F1 {:.3} | MCC {:.3} | ROC-AUC {:.3}",
+            self.precision, self.recall, self.f1, self.mcc, self.roc_auc
+        )
+    }
+
+    pub fn print(&self) {
+        let c = &self.confusion;
+        println!("               predicted 0  predicted 1");
+        println!(
+            "  survived 0   {:>11}  {:>11}",
+            c.true_negatives, c.false_positives
+        );
+        println!(
+            "  survived 1   {:>11}  {:>11}",
+            c.false_negatives, c.true_positives
+        );
+        for (name, value) in self.scores() {
+            println!("  {:<18} {:.4}", name, value);
+        }
+    }
+
+    /// Writes the report as json or csv, picked by the file extension.
+    pub fn save(&self, path: &str) {
+        if path.ends_with(".json") {
+            let json = serde_json::to_string_pretty(self).expect("could not serialize report");
+            std::fs::write(path, json).expect("could not write report");
+        } else if path.ends_with(".csv") {
+            let mut wtr = csv::Writer::from_path(path).expect("could not create file");
+            wtr.write_record(["metric", "value"])
+                .expect("could not write header");
+            for (name, value) in self.values() {
+                wtr.write_record([name, &value.to_string()])
+                    .expect("could not write record");
+            }
+        } else {
+            panic!("report {} should end in .json or .csv", path);
+        }
+    }
+}
+
+/// Probability that a random survivor is ranked above a random casualty, ties counting half.
+pub fn roc_auc(probabilities: &[f32], labels: &[u8]) -> f64 {
+    let mut scored: Vec<(f32, u8)> = probabilities
+        .iter()
+        .copied()
+        .zip(labels.iter().copied())
+        .collect();
+    // total order, so the NaN probabilities of a diverged model rank instead of panicking
+    scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // average ranks (1-based) over runs of equal probabilities
+    let mut positive_rank_sum = 0.0;
+    let mut i = 0;
+    while i < scored.len() {
+        let mut j = i + 1;
+        while j < scored.len() && scored[j].0 == scored[i].0 {
+            j += 1;
+        }
+        let rank = (i + 1 + j) as f64 / 2.0;
+        positive_rank_sum += rank * scored[i..j].iter().filter(|(_, l)| *l == 1).count() as f64;
+        i = j;
+    }
+
+    let positives = labels.iter().filter(|l| **l == 1).count() as f64;
+    let negatives = labels.len() as f64 - positives;
+    ratio(
+        positive_rank_sum - positives * (positives + 1.0) / 2.0,
+        positives * negatives,
+    )
+}
+
+/// Average precision: the precision at every survivor, ranked by descending probability.
+pub fn pr_auc(probabilities: &[f32], labels: &[u8]) -> f64 {
+    let mut scored: Vec<(f32, u8)> = probabilities
+        .iter()
+        .copied()
+        .zip(labels.iter().copied())
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let positives = labels.iter().filter(|l| **l == 1).count() as f64;
+    let mut true_positives = 0.0;
+    let mut precision_sum = 0.0;
+    for (i, (_, label)) in scored.iter().enumerate() {
+        if *label == 1 {
+            true_positives += 1.0;
+            precision_sum += true_positives / (i + 1) as f64;
+        }
+    }
+    ratio(precision_sum, positives)
+}
+
+/// Passengers whose probability falls in one equal-width bin.
+#[derive(Clone, Debug)]
+pub struct ReliabilityBin {
+    pub rows: usize,
+    pub mean_probability: f64,
+    pub survival_rate: f64,
+}
+
+/// Mean predicted probability against the observed survival rate in each of `bins` equal-width
+/// bins, empty bins left out.
+pub fn reliability(probabilities: &[f32], labels: &[u8], bins: usize) -> Vec<ReliabilityBin> {
+    let mut sums = vec![(0, 0.0, 0.0); bins];
+    for (probability, label) in probabilities.iter().zip(labels) {
+        let bin = ((*probability as f64 * bins as f64) as usize).min(bins - 1);
+        sums[bin].0 += 1;
+        sums[bin].1 += *probability as f64;
+        sums[bin].2 += *label as f64;
+    }
+    sums.into_iter()
+        .filter(|(rows, _, _)| *rows > 0)
+        .map(|(rows, probability, survived)| ReliabilityBin {
+            rows,
+            mean_probability: probability / rows as f64,
+            survival_rate: survived / rows as f64,
+        })
+        .collect()
+}
+
+/// Gap between predicted probability and survival rate, averaged over the bins weighted by
+/// their number of passengers.
+pub fn expected_calibration_error(probabilities: &[f32], labels: &[u8], bins: usize) -> f64 {
+    let gaps: f64 = reliability(probabilities, labels, bins)
+        .iter()
+        .map(|bin| bin.rows as f64 * (bin.mean_probability - bin.survival_rate).abs())
+        .sum();
+    ratio(gaps, probabilities.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBABILITIES: [f32; 4] = [0.1, 0.4, 0.35, 0.8];
+    const LABELS: [u8; 4] = [0, 0, 1, 1];
+
+    #[test]
+    fn confusion_matrix_counts_at_the_threshold() {
+        let confusion = ConfusionMatrix::new(&PROBABILITIES, &LABELS, 0.35);
+        assert_eq!(confusion.true_positives, 2);
+        assert_eq!(confusion.false_positives, 1);
+        assert_eq!(confusion.true_negatives, 1);
+        assert_eq!(confusion.false_negatives, 0);
+    }
+
+    #[test]
+    fn rates_follow_the_confusion_matrix() {
+        let metrics = Metrics::new(&PROBABILITIES, &LABELS, 0.5);
+        assert_eq!(metrics.accuracy, 0.75);
+        assert_eq!(metrics.precision, 1.0);
+        assert_eq!(metrics.recall, 0.5);
+        assert_eq!(metrics.specificity, 1.0);
+        assert!((metrics.f1 - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(metrics.balanced_accuracy, 0.75);
+    }
+
+    #[test]
+    fn report_lists_the_counts_then_the_scores() {
+        let metrics = Metrics::new(&PROBABILITIES, &LABELS, 0.5);
+        let names: Vec<&str> = metrics.values().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names.len(), 6 + metrics.scores().len());
+        assert_eq!(names[..2], ["rows", "threshold"]);
+        assert_eq!(names[6], "accuracy");
+    }
+
+    #[test]
+    fn empty_ratios_are_zero() {
+        let metrics = Metrics::new(&[0.1, 0.2], &[0, 0], 0.5);
+        assert_eq!(metrics.precision, 0.0);
+        assert_eq!(metrics.recall, 0.0);
+        assert_eq!(metrics.f1, 0.0);
+        assert_eq!(metrics.mcc, 0.0);
+    }
+
+    #[test]
+    fn coin_flip_losses() {
+        let metrics = Metrics::new(&[0.5; 4], &LABELS, 0.5);
+        assert!((metrics.log_loss - 2f64.ln()).abs() < 1e-12);
+        assert!((metrics.brier - 0.25).abs() < 1e-12);
+        assert!((metrics.ece - 0.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn roc_auc_ranks_survivors_above_casualties() {
+        assert_eq!(roc_auc(&PROBABILITIES, &LABELS), 0.75);
+        assert_eq!(roc_auc(&[0.1, 0.2, 0.8, 0.9], &LABELS), 1.0);
+        assert_eq!(roc_auc(&[0.5; 4], &LABELS), 0.5);
+        assert_eq!(roc_auc(&[0.1, 0.2], &[1, 1]), 0.0);
+    }
+
+    #[test]
+    fn roc_auc_ranks_nan_probabilities() {
+        let auc = roc_auc(&[f32::NAN, 0.2, f32::NAN, 0.9], &LABELS);
+        assert!(auc.is_finite());
+    }
+
+    #[test]
+    fn pr_auc_averages_the_precision_at_every_survivor() {
+        assert!((pr_auc(&PROBABILITIES, &LABELS) - 5.0 / 6.0).abs() < 1e-12);
+        assert_eq!(pr_auc(&[0.1, 0.2, 0.8, 0.9], &LABELS), 1.0);
+    }
+
+    #[test]
+    fn reliability_bins_probabilities_of_one_last() {
+        let bins = reliability(&[0.05, 0.15, 1.0], &[0, 1, 1], 10);
+        assert_eq!(bins.len(), 3);
+        assert_eq!(bins[2].rows, 1);
+        assert_eq!(bins[2].mean_probability, 1.0);
+        assert_eq!(bins[2].survival_rate, 1.0);
+    }
+
+    #[test]
+    fn expected_calibration_error_weights_bins_by_rows() {
+        // 0.2 against 0 for three passengers, 0.9 against 1 for one
+        let ece = expected_calibration_error(&[0.2, 0.2, 0.2, 0.9], &[0, 0, 0, 1], 10);
+        assert!((ece - (3.0 * 0.2 + 0.1) / 4.0).abs() < 1e-6);
+    }
+}
@@ -1,91 +1,453 @@
-#![allow(dead_code)]
+use std::str::FromStr;
+
 use burn::{
     config::Config,
+    constant,
     module::Module,
-    nn::{loss::BinaryCrossEntropyLossConfig, Linear, LinearConfig, Relu},
+    nn::{
+        loss::BinaryCrossEntropyLossConfig, BatchNorm, BatchNormConfig, Dropout, DropoutConfig,
+        Embedding, EmbeddingConfig as EmbeddingLayerConfig, LayerNorm, LayerNormConfig, Linear,
+        LinearConfig,
+    },
     prelude::Backend,
-    tensor::{ElementConversion, Int, Tensor},
+    tensor::{
+        activation::{gelu, leaky_relu, relu, sigmoid, silu},
+        ElementConversion, Int, Tensor,
+    },
 };
 
-use crate::data::{Batch, TestBatch};
+use crate::data::{Batch, Categorical, FeatureConfig};
+
+#[derive(Config, Debug, PartialEq)]
+pub enum Activation {
+    Relu,
+    Gelu,
+    LeakyRelu,
+    Silu,
+}
+
+impl FromStr for Activation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relu" => Ok(Activation::Relu),
+            "gelu" => Ok(Activation::Gelu),
+            "leaky-relu" => Ok(Activation::LeakyRelu),
+            "silu" => Ok(Activation::Silu),
+            _ => Err(format!(
+                "unknown activation {}, expected relu, gelu, leaky-relu or silu",
+                s
+            )),
+        }
+    }
+}
+
+impl Activation {
+    pub fn forward<B: Backend>(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        match self {
+            Activation::Relu => relu(x),
+            Activation::Gelu => gelu(x),
+            Activation::LeakyRelu => leaky_relu(x, 0.01),
+            Activation::Silu => silu(x),
+        }
+    }
+}
+
+// Stored in the model as a plain value, it has no weights.
+constant!(Activation);
+
+#[derive(Config, Debug, PartialEq)]
+pub enum Norm {
+    None,
+    Batch,
+    Layer,
+}
+
+impl FromStr for Norm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Norm::None),
+            "batch" => Ok(Norm::Batch),
+            "layer" => Ok(Norm::Layer),
+            _ => Err(format!("unknown norm {}, expected none, batch or layer", s)),
+        }
+    }
+}
+
+/// One hidden layer: linear, then the optional norm, activation and dropout.
+#[derive(Config, Debug)]
+pub struct LayerConfig {
+    pub width: usize,
+
+    #[config(default = "Activation::Relu")]
+    pub activation: Activation,
+
+    #[config(default = 0.0)]
+    pub dropout: f64,
+
+    #[config(default = "Norm::None")]
+    pub norm: Norm,
+
+    // Adds the layer input to its output, through a linear projection when the widths differ.
+    #[config(default = false)]
+    pub residual: bool,
+}
+
+impl LayerConfig {
+    fn init<B: Backend>(&self, input_size: usize, device: &B::Device) -> HiddenLayer<B> {
+        let batch_norm = match self.norm {
+            Norm::Batch => Some(BatchNormConfig::new(self.width).init(device)),
+            _ => None,
+        };
+        let layer_norm = match self.norm {
+            Norm::Layer => Some(LayerNormConfig::new(self.width).init(device)),
+            _ => None,
+        };
+        let projection = if self.residual && input_size != self.width {
+            Some(
+                LinearConfig::new(input_size, self.width)
+                    .with_bias(false)
+                    .init(device),
+            )
+        } else {
+            None
+        };
+
+        HiddenLayer {
+            linear: LinearConfig::new(input_size, self.width)
+                .with_bias(true)
+                .init(device),
+            batch_norm,
+            layer_norm,
+            activation: self.activation.clone(),
+            dropout: DropoutConfig::new(self.dropout).init(),
+            residual: self.residual,
+            projection,
+        }
+    }
+}
+
+/// A learned embedding for one categorical column, concatenated to the numeric features
+/// before the first hidden layer.
+#[derive(Config, Debug)]
+pub struct EmbeddingConfig {
+    pub column: Categorical,
+
+    #[config(default = 4)]
+    pub dim: usize,
+
+    // Set from the training split vocabulary (plus the unknown index) when training.
+    #[config(default = 0)]
+    pub vocab_size: usize,
+}
+
+impl FromStr for EmbeddingConfig {
+    type Err = String;
+
+    /// `column` or `column:dim`, e.g. `title:3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, dim) = match s.split_once(':') {
+            Some((column, dim)) => (
+                column,
+                Some(
+                    dim.parse()
+                        .map_err(|_| format!("invalid embedding dim {}", dim))?,
+                ),
+            ),
+            None => (s, None),
+        };
+        let config = EmbeddingConfig::new(column.parse()?);
+        Ok(match dim {
+            Some(dim) => config.with_dim(dim),
+            None => config,
+        })
+    }
+}
 
 #[derive(Config)]
 pub struct ModelConfig {
+    // Set from the enabled features when training.
     #[config(default = 6)]
     pub feature_size: usize,
 
-    #[config(default = 64)]
-    pub hidden_size: usize,
+    // No hidden layers at all gives logistic regression.
+    #[config(default = "vec![LayerConfig::new(64)]")]
+    pub hidden_layers: Vec<LayerConfig>,
+
+    #[config(default = "FeatureConfig::new()")]
+    pub features: FeatureConfig,
+
+    #[config(default = "Vec::new()")]
+    pub embeddings: Vec<EmbeddingConfig>,
 }
 
 impl ModelConfig {
+    pub fn embedded(&self) -> Vec<Categorical> {
+        self.embeddings.iter().map(|e| e.column.clone()).collect()
+    }
+
+    /// Name of every input column: the numeric `features` followed by each embedding
+    /// dimension, e.g. `title_embedding_0`.
+    pub fn input_names(&self, features: &[String]) -> Vec<String> {
+        let mut names = features.to_vec();
+        for embedding in &self.embeddings {
+            for i in 0..embedding.dim {
+                names.push(format!("{}_embedding_{}", embedding.column.name(), i));
+            }
+        }
+        names
+    }
+
+    /// Width of the first hidden layer input: numeric features plus every embedding.
+    pub fn input_size(&self) -> usize {
+        self.feature_size + self.embeddings.iter().map(|e| e.dim).sum::<usize>()
+    }
+
     pub fn init<B: Backend>(&self, device: &B::Device) -> Model<B> {
-        let input_layer = LinearConfig::new(self.feature_size, self.hidden_size)
-            .with_bias(true)
-            .init(device);
-        let output_layer = LinearConfig::new(self.hidden_size, 1)
+        let embeddings = self
+            .embeddings
+            .iter()
+            .map(|e| EmbeddingLayerConfig::new(e.vocab_size, e.dim).init(device))
+            .collect();
+
+        let mut input_size = self.input_size();
+        let mut layers = Vec::new();
+        for layer in &self.hidden_layers {
+            layers.push(layer.init(input_size, device));
+            input_size = layer.width;
+        }
+        let output_layer = LinearConfig::new(input_size, 1)
             .with_bias(true)
             .init(device);
-        let activation = Relu::new();
 
         Model {
-            input_layer,
-            activation,
+            embeddings,
+            layers,
             output_layer,
         }
     }
 }
 
+pub const DEFAULT_THRESHOLD: f32 = 0.5;
+
 pub struct ClassificationOutput<B: Backend> {
     pub loss: Tensor<B, 1>,
     pub accuracy: f32,
+    pub output: Tensor<B, 2>, // logits
+}
+
+/// Copies a tensor to a flat `Vec<f32>`, whatever the backend's float or int element.
+pub fn values<B: Backend, const D: usize>(tensor: Tensor<B, D>) -> Vec<f32> {
+    tensor
+        .into_data()
+        .convert::<f32>()
+        .to_vec()
+        .expect("to be ok")
+}
+
+#[derive(Clone, Debug)]
+pub struct Prediction {
+    pub id: u32,
+    pub probability: f32,
+    pub survived: u8,
+}
+
+#[derive(Module, Debug)]
+pub struct HiddenLayer<B: Backend> {
+    linear: Linear<B>,
+    batch_norm: Option<BatchNorm<B, 1>>,
+    layer_norm: Option<LayerNorm<B>>,
+    activation: Activation,
+    dropout: Dropout,
+    residual: bool,
+    projection: Option<Linear<B>>,
+}
+
+impl<B: Backend> HiddenLayer<B> {
+    pub fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
+        let x = self.linear.forward(input.clone());
+        let x = match (&self.batch_norm, &self.layer_norm) {
+            // BatchNorm expects [batch, channels, length]
+            (Some(norm), _) => {
+                let [rows, width] = x.dims();
+                norm.forward(x.reshape([rows, width, 1]))
+                    .reshape([rows, width])
+            }
+            (_, Some(norm)) => norm.forward(x),
+            _ => x,
+        };
+        let x = self.activation.forward(x);
+        let x = self.dropout.forward(x);
+
+        if !self.residual {
+            return x;
+        }
+        match &self.projection {
+            Some(projection) => x + projection.forward(input),
+            None => x + input,
+        }
+    }
 }
 
 #[derive(Module, Debug)]
 pub struct Model<B: Backend> {
-    input_layer: Linear<B>,
-    activation: Relu,
+    embeddings: Vec<Embedding<B>>,
+    layers: Vec<HiddenLayer<B>>,
     output_layer: Linear<B>,
 }
 
 impl<B: Backend> Model<B> {
-    pub fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
-        let x = input.detach();
-        let x = self.input_layer.forward(x);
-        let x = self.activation.forward(x);
+    pub fn forward(
+        &self,
+        input: Tensor<B, 2>,
+        categories: Option<Tensor<B, 2, Int>>,
+    ) -> Tensor<B, 2> {
+        let mut x = input.detach();
+        if let Some(categories) = categories {
+            let [rows, _] = categories.dims();
+            let mut columns = vec![x];
+            for (i, embedding) in self.embeddings.iter().enumerate() {
+                let indices = categories.clone().slice([0..rows, i..i + 1]);
+                // [rows, 1, dim] -> [rows, dim]
+                let embedded = embedding.forward(indices);
+                let [_, _, dim] = embedded.dims();
+                columns.push(embedded.reshape([rows, dim]));
+            }
+            x = Tensor::cat(columns, 1);
+        }
+        for layer in &self.layers {
+            x = layer.forward(x);
+        }
         self.output_layer.forward(x)
     }
 
-    pub fn forward_step(&self, batch: &Batch<B>, device: &B::Device) -> ClassificationOutput<B> {
-        let predictions = self.forward(batch.inputs.clone());
-        let labels = batch.labels.clone().int();
+    /// Survival probabilities, i.e. the sigmoid of the logits returned by `forward`.
+    pub fn probabilities(
+        &self,
+        input: Tensor<B, 2>,
+        categories: Option<Tensor<B, 2, Int>>,
+    ) -> Tensor<B, 2> {
+        sigmoid(self.forward(input, categories))
+    }
 
-        let accuracy = Self::accuracy(predictions.clone(), labels.clone());
+    /// `l1 * |w| + l2 * w²` summed over the weights of every linear layer, biases and
+    /// embeddings excluded.
+    pub fn penalty(&self, l1: f64, l2: f64) -> Tensor<B, 1> {
+        let mut weights = vec![self.output_layer.weight.val()];
+        for layer in &self.layers {
+            weights.push(layer.linear.weight.val());
+            if let Some(projection) = &layer.projection {
+                weights.push(projection.weight.val());
+            }
+        }
 
-        let loss_func = BinaryCrossEntropyLossConfig::new()
-            .with_logits(true)
-            .init(device);
-        let loss = loss_func.forward(predictions.clone(), labels.clone());
+        weights
+            .into_iter()
+            .map(|w| w.clone().abs().sum().mul_scalar(l1) + w.powf_scalar(2.0).sum().mul_scalar(l2))
+            .reduce(|a, b| a + b)
+            .expect("the output layer has weights")
+    }
 
-        ClassificationOutput { loss, accuracy }
+    /// Weights and bias of the output layer when it sits directly on the inputs, i.e. when
+    /// the model is a logistic regression.
+    pub fn coefficients(&self) -> Option<(Vec<f32>, f32)> {
+        if !self.layers.is_empty() {
+            return None;
+        }
+        let weights = values(self.output_layer.weight.val());
+        let bias = match &self.output_layer.bias {
+            Some(bias) => values(bias.val())[0],
+            None => 0.0,
+        };
+        Some((weights, bias))
     }
 
-    pub fn interface(&self, batch: &TestBatch<B>) -> Vec<(u32, i32)> {
-        let predictions = self.forward(batch.inputs.clone());
+    /// Checks that loaded weights have the shapes described by `config`.
+    pub fn check(&self, config: &ModelConfig) -> Result<(), String> {
+        if self.layers.len() != config.hidden_layers.len() {
+            return Err(format!(
+                "weights have {} hidden layers, expected {}",
+                self.layers.len(),
+                config.hidden_layers.len()
+            ));
+        }
+
+        if self.embeddings.len() != config.embeddings.len() {
+            return Err(format!(
+                "weights have {} embeddings, expected {}",
+                self.embeddings.len(),
+                config.embeddings.len()
+            ));
+        }
+        for (embedding, embedding_config) in self.embeddings.iter().zip(&config.embeddings) {
+            let dims = embedding.weight.dims();
+            if dims != [embedding_config.vocab_size, embedding_config.dim] {
+                return Err(format!(
+                    "{} embedding is {:?}, expected {:?}",
+                    embedding_config.column.name(),
+                    dims,
+                    [embedding_config.vocab_size, embedding_config.dim]
+                ));
+            }
+        }
 
-        let predictions: Tensor<B, 2, Int> = predictions.greater_elem(0.5).int();
-        let prediction: Vec<i32> = predictions.into_data().to_vec().expect("to be ok");
+        let mut input_size = config.input_size();
+        for (i, (layer, layer_config)) in self.layers.iter().zip(&config.hidden_layers).enumerate()
+        {
+            let dims = layer.linear.weight.dims();
+            if dims != [input_size, layer_config.width] {
+                return Err(format!(
+                    "hidden layer {} is {:?}, expected {:?}",
+                    i,
+                    dims,
+                    [input_size, layer_config.width]
+                ));
+            }
+            input_size = layer_config.width;
+        }
 
-        let mut output = Vec::new();
-        for (i, id) in batch.ids.iter().enumerate() {
-            output.push((*id, prediction[i]));
+        let output = self.output_layer.weight.dims();
+        if output != [input_size, 1] {
+            return Err(format!(
+                "output layer is {:?}, expected {:?}",
+                output,
+                [input_size, 1]
+            ));
         }
+        Ok(())
+    }
+
+    pub fn forward_step(
+        &self,
+        batch: &Batch<B>,
+        threshold: f32,
+        device: &B::Device,
+    ) -> ClassificationOutput<B> {
+        let predictions = self.forward(batch.inputs.clone(), batch.categories.clone());
+        let labels = batch.labels.clone().int();
+
+        let accuracy = Self::accuracy(predictions.clone(), labels.clone(), threshold);
+
+        let loss_func = BinaryCrossEntropyLossConfig::new()
+            .with_logits(true)
+            .init(device);
+        let loss = loss_func.forward(predictions.clone(), labels.clone());
 
-        output
+        ClassificationOutput {
+            loss,
+            accuracy,
+            output: predictions,
+        }
     }
 
-    fn accuracy(output: Tensor<B, 2>, targets: Tensor<B, 2, Int>) -> f32 {
-        let predictions: Tensor<B, 1, Int> = output.greater_elem(0.5).int().squeeze(1);
+    fn accuracy(output: Tensor<B, 2>, targets: Tensor<B, 2, Int>, threshold: f32) -> f32 {
+        let predictions: Tensor<B, 1, Int> = sigmoid(output)
+            .greater_equal_elem(threshold)
+            .int()
+            .squeeze(1);
         let num_predictions: usize = targets.dims().iter().product();
         let num_corrects = predictions
             .equal(targets.squeeze(1))
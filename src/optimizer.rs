@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use burn::{
+    config::Config,
+    grad_clipping::GradientClippingConfig,
+    optim::{
+        decay::WeightDecayConfig, momentum::MomentumConfig, AdamConfig, AdamWConfig, RmsPropConfig,
+        SgdConfig,
+    },
+};
+
+#[derive(Config, Debug, PartialEq)]
+pub enum OptimizerKind {
+    Adam,
+    AdamW,
+    Sgd,
+    RmsProp,
+}
+
+impl FromStr for OptimizerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adam" => Ok(OptimizerKind::Adam),
+            "adamw" => Ok(OptimizerKind::AdamW),
+            "sgd" => Ok(OptimizerKind::Sgd),
+            "rmsprop" => Ok(OptimizerKind::RmsProp),
+            _ => Err(format!(
+                "unknown optimizer {}, expected adam, adamw, sgd or rmsprop",
+                s
+            )),
+        }
+    }
+}
+
+/// Which burn optimizer to train with. The learning rate itself comes from the scheduler.
+#[derive(Config, Debug)]
+pub struct OptimizerConfig {
+    #[config(default = "OptimizerKind::Adam")]
+    pub kind: OptimizerKind,
+
+    // L2 penalty, decoupled from the gradient for AdamW.
+    #[config(default = 0.0)]
+    pub weight_decay: f64,
+
+    // Used by SGD and RMSProp.
+    #[config(default = 0.9)]
+    pub momentum: f64,
+
+    // Clips the gradient norm of every parameter.
+    pub grad_clip_norm: Option<f32>,
+}
+
+impl OptimizerConfig {
+    fn weight_decay(&self) -> Option<WeightDecayConfig> {
+        if self.weight_decay > 0.0 {
+            Some(WeightDecayConfig::new(self.weight_decay))
+        } else {
+            None
+        }
+    }
+
+    fn grad_clipping(&self) -> Option<GradientClippingConfig> {
+        self.grad_clip_norm.map(GradientClippingConfig::Norm)
+    }
+
+    pub fn adam(&self) -> AdamConfig {
+        AdamConfig::new()
+            .with_weight_decay(self.weight_decay())
+            .with_grad_clipping(self.grad_clipping())
+    }
+
+    pub fn adamw(&self) -> AdamWConfig {
+        AdamWConfig::new()
+            .with_weight_decay(self.weight_decay as f32)
+            .with_grad_clipping(self.grad_clipping())
+    }
+
+    pub fn sgd(&self) -> SgdConfig {
+        let momentum = if self.momentum > 0.0 {
+            Some(
+                MomentumConfig::new()
+                    .with_momentum(self.momentum)
+                    .with_dampening(0.0),
+            )
+        } else {
+            None
+        };
+
+        SgdConfig::new()
+            .with_weight_decay(self.weight_decay())
+            .with_momentum(momentum)
+            .with_gradient_clipping(self.grad_clipping())
+    }
+
+    pub fn rmsprop(&self) -> RmsPropConfig {
+        RmsPropConfig::new()
+            .with_momentum(self.momentum as f32)
+            .with_weight_decay(self.weight_decay())
+            .with_grad_clipping(self.grad_clipping())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weight_decay_and_clipping_only_when_set() {
+        let config = OptimizerConfig::new();
+        assert!(config.weight_decay().is_none());
+        assert!(config.grad_clipping().is_none());
+
+        let config = config
+            .with_weight_decay(1e-4)
+            .with_grad_clip_norm(Some(1.0));
+        assert_eq!(config.weight_decay().map(|decay| decay.penalty), Some(1e-4));
+        assert!(matches!(
+            config.grad_clipping(),
+            Some(GradientClippingConfig::Norm(norm)) if norm == 1.0
+        ));
+    }
+
+    #[test]
+    fn sgd_momentum_without_dampening() {
+        let sgd = serde_json::to_value(OptimizerConfig::new().sgd()).unwrap();
+        assert_eq!(sgd["momentum"]["momentum"], 0.9);
+        assert_eq!(sgd["momentum"]["dampening"], 0.0);
+
+        let plain = serde_json::to_value(OptimizerConfig::new().with_momentum(0.0).sgd()).unwrap();
+        assert!(plain["momentum"].is_null());
+    }
+
+    #[test]
+    fn parses_optimizers() {
+        assert_eq!("adamw".parse(), Ok(OptimizerKind::AdamW));
+        assert!("lbfgs".parse::<OptimizerKind>().is_err());
+    }
+}
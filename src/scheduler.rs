@@ -0,0 +1,291 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use burn::config::Config;
+
+#[derive(Config, Debug, PartialEq)]
+pub enum Schedule {
+    Constant,
+    // Multiplies the rate by gamma every `every` epochs.
+    Step {
+        every: usize,
+        gamma: f64,
+    },
+    // Anneals from the base rate down to min_lr over the whole run.
+    Cosine {
+        min_lr: f64,
+    },
+    // Ramps up from max_lr / 25 to max_lr over the first pct_start of the run, then anneals
+    // far below the starting rate.
+    OneCycle {
+        max_lr: f64,
+        pct_start: f64,
+    },
+    // Multiplies the rate by factor once the validation loss stalls for `patience` epochs.
+    Plateau {
+        factor: f64,
+        patience: usize,
+        min_lr: f64,
+    },
+}
+
+fn argument<T: FromStr>(arguments: &[&str], i: usize, default: T) -> Result<T, String> {
+    match arguments.get(i) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid schedule argument {}", value)),
+        None => Ok(default),
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    /// `constant`, `step[:every[:gamma]]`, `cosine[:min_lr]`, `one-cycle[:max_lr[:pct_start]]`
+    /// or `plateau[:factor[:patience[:min_lr]]]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or("");
+        let arguments: Vec<&str> = parts.collect();
+
+        match name {
+            "constant" => Ok(Schedule::Constant),
+            "step" => Ok(Schedule::Step {
+                every: argument(&arguments, 0, 10)?,
+                gamma: argument(&arguments, 1, 0.5)?,
+            }),
+            "cosine" => Ok(Schedule::Cosine {
+                min_lr: argument(&arguments, 0, 0.0)?,
+            }),
+            "one-cycle" => Ok(Schedule::OneCycle {
+                max_lr: argument(&arguments, 0, 0.05)?,
+                pct_start: argument(&arguments, 1, 0.3)?,
+            }),
+            "plateau" => match argument(&arguments, 1, 2)? {
+                0 => Err("plateau patience must be at least 1".to_string()),
+                patience => Ok(Schedule::Plateau {
+                    factor: argument(&arguments, 0, 0.5)?,
+                    patience,
+                    min_lr: argument(&arguments, 2, 1e-5)?,
+                }),
+            },
+            _ => Err(format!(
+                "unknown schedule {}, expected constant, step, cosine, one-cycle or plateau",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Config, Debug)]
+pub struct SchedulerConfig {
+    #[config(default = "Schedule::Constant")]
+    pub schedule: Schedule,
+
+    // Linear ramp from zero to the scheduled rate over the first epochs.
+    #[config(default = 0)]
+    pub warmup_epochs: usize,
+}
+
+/// What the plateau schedule has seen so far, checkpointed so a resumed run picks up where it
+/// stopped.
+#[derive(Config, Debug)]
+pub struct SchedulerState {
+    // Reductions applied so far.
+    #[config(default = 1.0)]
+    pub scale: f64,
+
+    pub best_loss: Option<f64>,
+
+    #[config(default = 0)]
+    pub stale_epochs: usize,
+}
+
+/// Learning rate for every optimizer step, driven by the training loop.
+pub struct Scheduler {
+    config: SchedulerConfig,
+    base_lr: f64,
+    steps_per_epoch: usize,
+    total_steps: usize,
+    state: SchedulerState,
+}
+
+impl Scheduler {
+    pub fn new(
+        config: &SchedulerConfig,
+        base_lr: f64,
+        steps_per_epoch: usize,
+        epochs: usize,
+    ) -> Scheduler {
+        if let Schedule::Plateau { patience, .. } = config.schedule {
+            assert!(patience >= 1, "plateau patience must be at least 1");
+        }
+        Scheduler {
+            config: config.clone(),
+            base_lr,
+            steps_per_epoch: steps_per_epoch.max(1),
+            total_steps: (steps_per_epoch * epochs).max(1),
+            state: SchedulerState::new(),
+        }
+    }
+
+    pub fn state(&self) -> &SchedulerState {
+        &self.state
+    }
+
+    pub fn restore(&mut self, state: SchedulerState) {
+        self.state = state;
+    }
+
+    pub fn lr(&self, step: usize) -> f64 {
+        let progress = step as f64 / self.total_steps as f64;
+        let lr = match &self.config.schedule {
+            Schedule::Constant => self.base_lr,
+            Schedule::Step { every, gamma } => {
+                let epoch = step / self.steps_per_epoch;
+                self.base_lr * gamma.powi((epoch / (*every).max(1)) as i32)
+            }
+            Schedule::Cosine { min_lr } => {
+                min_lr + (self.base_lr - min_lr) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+            Schedule::OneCycle { max_lr, pct_start } => {
+                let initial = max_lr / 25.0;
+                let last = initial / 1e4;
+                if progress < *pct_start {
+                    let t = progress / pct_start;
+                    initial + (max_lr - initial) * (1.0 - (PI * t).cos()) / 2.0
+                } else {
+                    let t = (progress - pct_start) / (1.0 - pct_start).max(f64::EPSILON);
+                    last + (max_lr - last) * (1.0 + (PI * t).cos()) / 2.0
+                }
+            }
+            Schedule::Plateau { .. } => self.base_lr * self.state.scale,
+        };
+
+        let warmup_steps = self.config.warmup_epochs * self.steps_per_epoch;
+        if step < warmup_steps {
+            lr * (step + 1) as f64 / warmup_steps as f64
+        } else {
+            lr
+        }
+    }
+
+    /// Feeds the epoch's validation loss to the plateau schedule.
+    pub fn end_epoch(&mut self, valid_loss: f64) {
+        let Schedule::Plateau {
+            factor,
+            patience,
+            min_lr,
+        } = &self.config.schedule
+        else {
+            return;
+        };
+
+        let state = &mut self.state;
+        match state.best_loss {
+            Some(best) if valid_loss >= best => state.stale_epochs += 1,
+            _ => {
+                state.best_loss = Some(valid_loss);
+                state.stale_epochs = 0;
+            }
+        }
+        if state.stale_epochs >= *patience {
+            state.scale = (state.scale * factor).max(min_lr / self.base_lr);
+            state.stale_epochs = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_LR: f64 = 0.1;
+
+    // 10 steps per epoch over 10 epochs
+    fn scheduler(schedule: &str, warmup_epochs: usize) -> Scheduler {
+        let config = SchedulerConfig::new()
+            .with_schedule(schedule.parse().unwrap())
+            .with_warmup_epochs(warmup_epochs);
+        Scheduler::new(&config, BASE_LR, 10, 10)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn step_decays_every_few_epochs() {
+        let scheduler = scheduler("step:2:0.5", 0);
+        assert_close(scheduler.lr(0), 0.1);
+        assert_close(scheduler.lr(19), 0.1);
+        assert_close(scheduler.lr(20), 0.05);
+        assert_close(scheduler.lr(45), 0.025);
+    }
+
+    #[test]
+    fn cosine_anneals_to_the_minimum() {
+        let scheduler = scheduler("cosine:0.01", 0);
+        assert_close(scheduler.lr(0), 0.1);
+        assert_close(scheduler.lr(50), 0.055);
+        assert_close(scheduler.lr(100), 0.01);
+    }
+
+    #[test]
+    fn one_cycle_peaks_after_pct_start() {
+        let scheduler = scheduler("one-cycle:0.05:0.3", 0);
+        assert_close(scheduler.lr(0), 0.002);
+        assert_close(scheduler.lr(30), 0.05);
+        assert_close(scheduler.lr(100), 0.002 / 1e4);
+        assert!(scheduler.lr(15) < scheduler.lr(30));
+        assert!(scheduler.lr(60) < scheduler.lr(30));
+    }
+
+    #[test]
+    fn warmup_ramps_up_linearly() {
+        let scheduler = scheduler("constant", 2);
+        assert_close(scheduler.lr(0), 0.005);
+        assert_close(scheduler.lr(9), 0.05);
+        assert_close(scheduler.lr(19), 0.1);
+        assert_close(scheduler.lr(20), 0.1);
+    }
+
+    #[test]
+    fn plateau_cuts_the_rate_after_patience_stale_epochs() {
+        let mut scheduler = scheduler("plateau:0.5:2:0.02", 0);
+        for loss in [1.0, 1.0] {
+            scheduler.end_epoch(loss);
+        }
+        assert_close(scheduler.lr(0), 0.1);
+        scheduler.end_epoch(1.1);
+        assert_close(scheduler.lr(0), 0.05);
+
+        // an improvement resets the count
+        scheduler.end_epoch(0.9);
+        scheduler.end_epoch(0.95);
+        assert_close(scheduler.lr(0), 0.05);
+
+        for _ in 0..10 {
+            scheduler.end_epoch(1.0);
+        }
+        assert_close(scheduler.lr(0), 0.02);
+    }
+
+    #[test]
+    fn parses_schedules() {
+        assert_eq!(
+            "step".parse(),
+            Ok(Schedule::Step {
+                every: 10,
+                gamma: 0.5
+            })
+        );
+        assert!("plateau:0.5:0".parse::<Schedule>().is_err());
+        assert!("cosine:fast".parse::<Schedule>().is_err());
+        assert!("linear".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,621 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use burn::{config::Config, tensor::backend::AutodiffBackend};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    classifier::ClassifierConfig,
+    cv::{cross_validate, mean_std, CvConfig},
+    data::{RawData, TitanicRecord},
+    model::{LayerConfig, ModelConfig},
+    training::ExpConfig,
+};
+
+/// Values a parameter can take. Ranges are sampled by the random strategies and cut into
+/// `grid_points` evenly spaced values by the grid.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Domain {
+    Values(Vec<Value>),
+    Uniform([f64; 2]),
+    LogUniform([f64; 2]),
+    IntUniform([i64; 2]),
+}
+
+impl Domain {
+    fn sample(&self, rng: &mut StdRng) -> Value {
+        match self {
+            Domain::Values(values) => values[rng.gen_range(0..values.len())].clone(),
+            Domain::Uniform([low, high]) => json!(rng.gen_range(*low..=*high)),
+            Domain::LogUniform([low, high]) => {
+                json!(rng.gen_range(low.ln()..=high.ln()).exp())
+            }
+            Domain::IntUniform([low, high]) => json!(rng.gen_range(*low..=*high)),
+        }
+    }
+
+    fn grid(&self, points: usize) -> Vec<Value> {
+        let steps = |low: f64, high: f64| -> Vec<f64> {
+            if points < 2 {
+                return vec![low];
+            }
+            (0..points)
+                .map(|i| low + (high - low) * i as f64 / (points - 1) as f64)
+                .collect()
+        };
+
+        match self {
+            Domain::Values(values) => values.clone(),
+            Domain::Uniform([low, high]) => {
+                steps(*low, *high).into_iter().map(|v| json!(v)).collect()
+            }
+            Domain::LogUniform([low, high]) => steps(low.ln(), high.ln())
+                .into_iter()
+                .map(|v| json!(v.exp()))
+                .collect(),
+            Domain::IntUniform([low, high]) => {
+                let mut values: Vec<i64> = steps(*low as f64, *high as f64)
+                    .into_iter()
+                    .map(|v| v.round() as i64)
+                    .collect();
+                values.dedup();
+                values.into_iter().map(|v| json!(v)).collect()
+            }
+        }
+    }
+}
+
+/// One searched setting, addressed by its dotted path in the experiment (`exp.`) or model
+/// (`model.`) config, e.g. `exp.learning_rate` or `model.features.deck`. `*` stands for every
+/// element of a list, as in `model.hidden_layers.*.dropout`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Parameter {
+    pub path: String,
+    #[serde(flatten)]
+    pub domain: Domain,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchSpace {
+    pub parameters: Vec<Parameter>,
+}
+
+impl SearchSpace {
+    pub fn load(path: &str) -> SearchSpace {
+        let json = std::fs::read_to_string(path).expect("could not read search space");
+        serde_json::from_str(&json)
+            .unwrap_or_else(|err| panic!("invalid search space {}: {}", path, err))
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> Vec<Value> {
+        self.parameters
+            .iter()
+            .map(|p| p.domain.sample(rng))
+            .collect()
+    }
+
+    /// Every combination of the parameters' grid values.
+    fn grid(&self, points: usize) -> Vec<Vec<Value>> {
+        self.parameters
+            .iter()
+            .fold(vec![Vec::new()], |combinations, parameter| {
+                let values = parameter.domain.grid(points);
+                combinations
+                    .iter()
+                    .flat_map(|combination| {
+                        values.iter().map(move |value| {
+                            let mut combination = combination.clone();
+                            combination.push(value.clone());
+                            combination
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    /// Copies of the base configs with `values` applied, in parameter order. Widths like
+    /// `[128, 64]` for `model.hidden_layers` become layers with the settings of the base
+    /// model's first layer.
+    pub fn apply(
+        &self,
+        values: &[Value],
+        exp: &ExpConfig,
+        model: &ModelConfig,
+    ) -> (ExpConfig, ModelConfig) {
+        let mut root = json!({
+            "exp": serde_json::to_value(exp).expect("could not serialize experiment config"),
+            "model": serde_json::to_value(model).expect("could not serialize model config"),
+        });
+        let template = model
+            .hidden_layers
+            .first()
+            .cloned()
+            .unwrap_or_else(|| LayerConfig::new(64));
+
+        for (parameter, value) in self.parameters.iter().zip(values) {
+            let value = match value {
+                Value::Array(widths)
+                    if parameter.path.ends_with("hidden_layers")
+                        && widths.iter().all(Value::is_number) =>
+                {
+                    let layers: Vec<LayerConfig> = widths
+                        .iter()
+                        .map(|width| {
+                            let mut layer = template.clone();
+                            layer.width =
+                                width.as_u64().expect("layer widths should be integers") as usize;
+                            layer
+                        })
+                        .collect();
+                    serde_json::to_value(layers).expect("could not serialize layers")
+                }
+                value => value.clone(),
+            };
+            let keys: Vec<&str> = parameter.path.split('.').collect();
+            if !set(&mut root, &keys, &value) {
+                panic!(
+                    "search path {} is not in the experiment or model config",
+                    parameter.path
+                );
+            }
+        }
+
+        let exp = serde_json::from_value(root["exp"].take())
+            .unwrap_or_else(|err| panic!("invalid experiment config in search: {}", err));
+        let model = serde_json::from_value(root["model"].take())
+            .unwrap_or_else(|err| panic!("invalid model config in search: {}", err));
+        (exp, model)
+    }
+}
+
+/// Replaces the existing value at `keys`, false when the path does not exist.
+fn set(node: &mut Value, keys: &[&str], value: &Value) -> bool {
+    let Some((key, rest)) = keys.split_first() else {
+        *node = value.clone();
+        return true;
+    };
+
+    match (node, *key) {
+        (Value::Array(items), "*") => items.iter_mut().all(|item| set(item, rest, value)),
+        (Value::Array(items), key) => {
+            match key.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                Some(item) => set(item, rest, value),
+                None => false,
+            }
+        }
+        (Value::Object(fields), key) => match fields.get_mut(key) {
+            Some(field) => set(field, rest, value),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+#[derive(Config, Debug, PartialEq)]
+pub enum Strategy {
+    Grid,
+    Random,
+    // Races `trials` random configurations, keeping the best 1 / eta at each budget.
+    SuccessiveHalving,
+    // Successive halving brackets trading the number of trials against their minimum budget.
+    Hyperband,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(Strategy::Grid),
+            "random" => Ok(Strategy::Random),
+            "halving" => Ok(Strategy::SuccessiveHalving),
+            "hyperband" => Ok(Strategy::Hyperband),
+            _ => Err(format!(
+                "unknown strategy {}, expected grid, random, halving or hyperband",
+                s
+            )),
+        }
+    }
+}
+
+/// Trials are scored by their mean cross-validated loss. Budgets are epochs, up to the base
+/// experiment's.
+#[derive(Config, Debug)]
+pub struct SearchConfig {
+    #[config(default = "Strategy::Random")]
+    pub strategy: Strategy,
+
+    // Configurations sampled by random search and successive halving.
+    #[config(default = 20)]
+    pub trials: usize,
+
+    // Values per range parameter in grid search.
+    #[config(default = 3)]
+    pub grid_points: usize,
+
+    // Budget multiplier between rungs of successive halving and Hyperband.
+    #[config(default = 3)]
+    pub eta: usize,
+
+    #[config(default = 1)]
+    pub min_epochs: usize,
+
+    #[config(default = "CvConfig::new().with_folds(3)")]
+    pub cv: CvConfig,
+}
+
+/// One row of the results table: a configuration cross-validated for `epochs` epochs.
+#[derive(Clone, Debug)]
+pub struct TrialResult {
+    pub trial: usize,
+    pub bracket: usize,
+    pub epochs: usize,
+    pub values: Vec<Value>,
+    pub loss: f64,
+    pub loss_std: f64,
+    pub accuracy: f64,
+    pub accuracy_std: f64,
+}
+
+struct Search<'a, B: AutodiffBackend> {
+    records: &'a [TitanicRecord],
+    space: &'a SearchSpace,
+    search: &'a SearchConfig,
+    exp: &'a ExpConfig,
+    model: &'a ModelConfig,
+    results_path: &'a str,
+    results: Vec<TrialResult>,
+    device: &'a B::Device,
+}
+
+impl<B: AutodiffBackend> Search<'_, B> {
+    fn evaluate(&mut self, trial: usize, bracket: usize, values: &[Value], epochs: usize) -> f64 {
+        let (mut exp, model) = self.space.apply(values, self.exp, self.model);
+        exp.epochs = epochs;
+        let config = ClassifierConfig::NeuralNet {
+            experiment: exp,
+            model,
+        };
+        let result =
+            cross_validate::<B>(self.records, &self.search.cv, &config, false, self.device);
+
+        let (loss, loss_std) = mean_std(&result.losses);
+        let (accuracy, accuracy_std) = mean_std(&result.accuracies);
+        println!(
+            "[Trial {} - {} epochs] Loss {:.3} ± {:.3} | Accuracy {:.3} ± {:.3} % | {}",
+            trial,
+            epochs,
+            loss,
+            loss_std,
+            accuracy,
+            accuracy_std,
+            describe(self.space, values)
+        );
+
+        self.results.push(TrialResult {
+            trial,
+            bracket,
+            epochs,
+            values: values.to_vec(),
+            loss,
+            loss_std,
+            accuracy,
+            accuracy_std,
+        });
+        // rewritten after every trial so an interrupted search keeps its results
+        write_results(self.results_path, self.space, &self.results);
+        loss
+    }
+
+    /// Evaluates every configuration at `budget` epochs, then keeps the best 1 / eta of them
+    /// for `eta` times the budget until the full budget is reached.
+    fn successive_halving(
+        &mut self,
+        bracket: usize,
+        first_trial: usize,
+        configurations: Vec<Vec<Value>>,
+        budget: usize,
+    ) {
+        let eta = self.search.eta.max(2);
+        let budgets = rung_budgets(budget, self.exp.epochs, eta);
+        let mut survivors: Vec<(usize, Vec<Value>)> = configurations
+            .into_iter()
+            .enumerate()
+            .map(|(i, values)| (first_trial + i, values))
+            .collect();
+
+        for (rung, budget) in budgets.iter().enumerate() {
+            let mut scored: Vec<(f64, usize, Vec<Value>)> = survivors
+                .into_iter()
+                .map(|(trial, values)| {
+                    let loss = self.evaluate(trial, bracket, &values, *budget);
+                    (loss, trial, values)
+                })
+                .collect();
+            if rung + 1 == budgets.len() {
+                break;
+            }
+
+            scored.sort_by(|a, b| by_loss(a.0, b.0));
+            scored.truncate(scored.len().div_ceil(eta));
+            survivors = scored
+                .into_iter()
+                .map(|(_, trial, values)| (trial, values))
+                .collect();
+        }
+    }
+}
+
+/// Epochs of every rung of successive halving, from `budget` up to `max_epochs` by factors
+/// of `eta`.
+fn rung_budgets(budget: usize, max_epochs: usize, eta: usize) -> Vec<usize> {
+    let mut budgets = vec![budget.clamp(1, max_epochs)];
+    while let Some(&last) = budgets.last().filter(|last| **last < max_epochs) {
+        budgets.push((last * eta).min(max_epochs));
+    }
+    budgets
+}
+
+/// `(bracket, configurations, first budget)` of every Hyperband bracket, the one racing the
+/// most configurations from the smallest budget first.
+fn hyperband_brackets(
+    max_epochs: usize,
+    min_epochs: usize,
+    eta: usize,
+) -> Vec<(usize, usize, usize)> {
+    let eta = eta.max(2) as f64;
+    let ratio = max_epochs as f64 / min_epochs.max(1) as f64;
+    let s_max = (ratio.ln() / eta.ln() + 1e-9).floor().max(0.0) as usize;
+    (0..=s_max)
+        .rev()
+        .map(|s| {
+            let n = ((s_max + 1) as f64 / (s + 1) as f64 * eta.powi(s as i32)).ceil() as usize;
+            let budget = (max_epochs as f64 / eta.powi(s as i32)).round() as usize;
+            (s, n, budget)
+        })
+        .collect()
+}
+
+/// Orders losses with the NaN of a diverged trial after every real loss.
+fn by_loss(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.total_cmp(&b),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+fn describe(space: &SearchSpace, values: &[Value]) -> String {
+    space
+        .parameters
+        .iter()
+        .zip(values)
+        .map(|(parameter, value)| format!("{}={}", parameter.path, cell(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+pub fn write_results(path: &str, space: &SearchSpace, results: &[TrialResult]) {
+    let mut wtr = csv::Writer::from_path(path).expect("could not create file");
+
+    let mut header = vec![
+        "trial".to_string(),
+        "bracket".to_string(),
+        "epochs".to_string(),
+    ];
+    header.extend(space.parameters.iter().map(|p| p.path.clone()));
+    header.extend(
+        ["loss", "loss_std", "accuracy", "accuracy_std"]
+            .iter()
+            .map(|s| s.to_string()),
+    );
+    wtr.write_record(&header).expect("could not write header");
+
+    for result in results {
+        let mut record = vec![
+            result.trial.to_string(),
+            result.bracket.to_string(),
+            result.epochs.to_string(),
+        ];
+        record.extend(result.values.iter().map(cell));
+        record.extend(
+            [
+                result.loss,
+                result.loss_std,
+                result.accuracy,
+                result.accuracy_std,
+            ]
+            .iter()
+            .map(|v| v.to_string()),
+        );
+        wtr.write_record(&record).expect("could not write record");
+    }
+}
+
+/// Searches `space` around the base configs and saves the best full-budget configuration to
+/// `<output>.exp.json` and `<output>.model.json`, ready for `train --config --model-config`.
+#[allow(clippy::too_many_arguments)]
+pub fn run<B: AutodiffBackend>(
+    train_paths: &[String],
+    space: &SearchSpace,
+    search: &SearchConfig,
+    exp: ExpConfig,
+    model: ModelConfig,
+    results_path: &str,
+    output: &str,
+    device: B::Device,
+) {
+    assert!(exp.epochs >= 1, "search needs at least 1 epoch");
+    assert!(
+        search.min_epochs >= 1 && search.min_epochs <= exp.epochs,
+        "--min-epochs must be between 1 and --epochs ({}), got {}",
+        exp.epochs,
+        search.min_epochs
+    );
+    let raw_data = RawData::from_paths(train_paths);
+    let mut rng = StdRng::seed_from_u64(exp.seed);
+    let max_epochs = exp.epochs;
+    let mut state = Search::<B> {
+        records: raw_data.get_all_rows(),
+        space,
+        search,
+        exp: &exp,
+        model: &model,
+        results_path,
+        results: Vec::new(),
+        device: &device,
+    };
+
+    match search.strategy {
+        Strategy::Grid => {
+            for (trial, values) in space.grid(search.grid_points).iter().enumerate() {
+                state.evaluate(trial, 0, values, max_epochs);
+            }
+        }
+        Strategy::Random => {
+            for trial in 0..search.trials {
+                let values = space.sample(&mut rng);
+                state.evaluate(trial, 0, &values, max_epochs);
+            }
+        }
+        Strategy::SuccessiveHalving => {
+            let configurations = (0..search.trials).map(|_| space.sample(&mut rng)).collect();
+            state.successive_halving(0, 0, configurations, search.min_epochs);
+        }
+        Strategy::Hyperband => {
+            let mut trial = 0;
+            for (bracket, n, budget) in
+                hyperband_brackets(max_epochs, search.min_epochs, search.eta)
+            {
+                let configurations = (0..n).map(|_| space.sample(&mut rng)).collect();
+                state.successive_halving(bracket, trial, configurations, budget);
+                trial += n;
+            }
+        }
+    }
+
+    let best = state
+        .results
+        .iter()
+        .filter(|r| r.epochs == max_epochs)
+        .min_by(|a, b| by_loss(a.loss, b.loss))
+        .expect("search should evaluate at least one configuration")
+        .clone();
+    println!(
+        "*** [Search - best trial {}] Loss {:.3} ± {:.3} | Accuracy {:.3} ± {:.3} % | {}",
+        best.trial,
+        best.loss,
+        best.loss_std,
+        best.accuracy,
+        best.accuracy_std,
+        describe(space, &best.values)
+    );
+
+    let (best_exp, best_model) = space.apply(&best.values, &exp, &model);
+    let exp_path = format!("{}.exp.json", output);
+    let model_path = format!("{}.model.json", output);
+    best_exp
+        .save(&exp_path)
+        .expect("could not save experiment config");
+    best_model
+        .save(&model_path)
+        .expect("could not save model config");
+    println!(
+        "Saved {} and {}, train with --config {} --model-config {}",
+        exp_path, model_path, exp_path, model_path
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space(parameters: Value) -> SearchSpace {
+        serde_json::from_value(json!({ "parameters": parameters })).unwrap()
+    }
+
+    #[test]
+    fn grid_crosses_every_parameter() {
+        let space = space(json!([
+            { "path": "exp.batch_size", "values": [32, 64, 128] },
+            { "path": "exp.learning_rate", "log_uniform": [1e-4, 1e-2] },
+            { "path": "exp.epochs", "int_uniform": [1, 2] },
+        ]));
+        let grid = space.grid(4);
+        // rounding leaves 2 of the 4 integer points
+        assert_eq!(grid.len(), 3 * 4 * 2);
+        assert_eq!(grid[0][0], json!(32));
+        assert_eq!(grid[23][2], json!(2));
+
+        let rates = space.parameters[1].domain.grid(3);
+        assert!((rates[1].as_f64().unwrap() - 1e-3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn applies_nested_experiment_and_model_values() {
+        let space = space(json!([
+            { "path": "exp.optimizer.weight_decay", "values": [] },
+            { "path": "model.hidden_layers", "values": [] },
+            { "path": "model.hidden_layers.*.dropout", "values": [] },
+            { "path": "model.features.deck", "values": [] },
+        ]));
+        let values = [json!(0.01), json!([128, 32]), json!(0.2), json!(false)];
+        let (exp, model) = space.apply(&values, &ExpConfig::new(), &ModelConfig::new());
+
+        assert_eq!(exp.optimizer.weight_decay, 0.01);
+        let widths: Vec<usize> = model.hidden_layers.iter().map(|l| l.width).collect();
+        assert_eq!(widths, vec![128, 32]);
+        assert!(model.hidden_layers.iter().all(|l| l.dropout == 0.2));
+        assert!(!model.features.deck);
+        assert!(model.features.title);
+    }
+
+    #[test]
+    #[should_panic(expected = "search path exp.lr is not in the experiment or model config")]
+    fn rejects_unknown_paths() {
+        let space = space(json!([{ "path": "exp.lr", "values": [] }]));
+        space.apply(&[json!(0.1)], &ExpConfig::new(), &ModelConfig::new());
+    }
+
+    #[test]
+    fn set_replaces_existing_values_only() {
+        let mut node = json!({ "layers": [{ "width": 8 }, { "width": 16 }] });
+        assert!(set(&mut node, &["layers", "1", "width"], &json!(4)));
+        assert_eq!(node["layers"][1]["width"], 4);
+        assert!(!set(&mut node, &["layers", "2", "width"], &json!(4)));
+        assert!(!set(&mut node, &["layers", "*", "depth"], &json!(4)));
+    }
+
+    #[test]
+    fn rungs_multiply_the_budget_by_eta() {
+        assert_eq!(rung_budgets(1, 27, 3), vec![1, 3, 9, 27]);
+        assert_eq!(rung_budgets(3, 10, 3), vec![3, 9, 10]);
+        assert_eq!(rung_budgets(0, 5, 2), vec![1, 2, 4, 5]);
+        assert_eq!(rung_budgets(5, 5, 3), vec![5]);
+    }
+
+    #[test]
+    fn hyperband_trades_configurations_for_budget() {
+        assert_eq!(
+            hyperband_brackets(27, 1, 3),
+            vec![(3, 27, 1), (2, 12, 3), (1, 6, 9), (0, 4, 27)]
+        );
+        assert_eq!(hyperband_brackets(10, 10, 3), vec![(0, 1, 10)]);
+    }
+
+    #[test]
+    fn diverged_trials_rank_last() {
+        let mut losses = [f64::NAN, 0.4, 0.3];
+        losses.sort_by(|a, b| by_loss(*a, *b));
+        assert_eq!(losses[..2], [0.3, 0.4]);
+        assert!(losses[2].is_nan());
+    }
+}
@@ -0,0 +1,214 @@
+use std::str::FromStr;
+
+use burn::{config::Config, tensor::backend::AutodiffBackend};
+
+use crate::{
+    classifier::{self, validation_records},
+    data::RawData,
+    metrics::{ConfusionMatrix, Metrics},
+    model::DEFAULT_THRESHOLD,
+};
+
+/// What the decision threshold is tuned for.
+#[derive(Config, Debug, PartialEq)]
+pub enum Objective {
+    Accuracy,
+    F1,
+    // Youden's J statistic, recall + specificity - 1.
+    Youden,
+    // Total cost of the mistakes, lower being better.
+    Cost {
+        false_positive: f64,
+        false_negative: f64,
+    },
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    /// `accuracy`, `f1`, `youden` or `cost[:false_positive[:false_negative]]`, costs
+    /// defaulting to 1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let mut cost = |what: &str| -> Result<f64, String> {
+            match parts.next() {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| format!("invalid {} cost {}", what, value)),
+                None => Ok(1.0),
+            }
+        };
+        match name {
+            "accuracy" => Ok(Objective::Accuracy),
+            "f1" => Ok(Objective::F1),
+            "youden" => Ok(Objective::Youden),
+            "cost" => Ok(Objective::Cost {
+                false_positive: cost("false positive")?,
+                false_negative: cost("false negative")?,
+            }),
+            _ => Err(format!(
+                "unknown objective {}, expected accuracy, f1, youden or cost[:fp[:fn]]",
+                s
+            )),
+        }
+    }
+}
+
+impl Objective {
+    /// The metric itself: a rate, J between -1 and 1, or the total cost.
+    pub fn value(&self, confusion: &ConfusionMatrix) -> f64 {
+        let tp = confusion.true_positives as f64;
+        let fp = confusion.false_positives as f64;
+        let tn = confusion.true_negatives as f64;
+        let fn_ = confusion.false_negatives as f64;
+        let ratio = |a: f64, b: f64| if b == 0.0 { 0.0 } else { a / b };
+        match self {
+            Objective::Accuracy => ratio(tp + tn, tp + fp + tn + fn_),
+            Objective::F1 => ratio(2.0 * tp, 2.0 * tp + fp + fn_),
+            Objective::Youden => ratio(tp, tp + fn_) + ratio(tn, tn + fp) - 1.0,
+            Objective::Cost {
+                false_positive,
+                false_negative,
+            } => false_positive * fp + false_negative * fn_,
+        }
+    }
+
+    /// Higher is better, costs being negated.
+    pub fn score(&self, confusion: &ConfusionMatrix) -> f64 {
+        match self {
+            Objective::Cost { .. } => -self.value(confusion),
+            _ => self.value(confusion),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Objective::Accuracy => "Accuracy",
+            Objective::F1 => "F1",
+            Objective::Youden => "Youden's J",
+            Objective::Cost { .. } => "Cost",
+        }
+    }
+}
+
+/// Tries every distinct probability as the threshold, and 0.5, returning the one with the best
+/// score. Ties go to the threshold closest to 0.5.
+pub fn tune(probabilities: &[f32], labels: &[u8], objective: &Objective) -> (f32, f64) {
+    let mut candidates: Vec<f32> = probabilities.to_vec();
+    candidates.push(DEFAULT_THRESHOLD);
+    candidates.sort_by(|a, b| a.total_cmp(b));
+    candidates.dedup();
+
+    let mut best = (
+        DEFAULT_THRESHOLD,
+        objective.score(&ConfusionMatrix::new(
+            probabilities,
+            labels,
+            DEFAULT_THRESHOLD,
+        )),
+    );
+    for threshold in candidates {
+        let score = objective.score(&ConfusionMatrix::new(probabilities, labels, threshold));
+        let closer = (threshold - DEFAULT_THRESHOLD).abs() < (best.0 - DEFAULT_THRESHOLD).abs();
+        if score > best.1 || (score == best.1 && closer) {
+            best = (threshold, score);
+        }
+    }
+    best
+}
+
+/// Picks the threshold for `objective` on the validation data, the model's own validation
+/// data unless `valid_path` is given, and saves it with the model.
+pub fn run<B: AutodiffBackend>(
+    model_path: &str,
+    objective: &Objective,
+    valid_path: Option<&str>,
+    device: B::Device,
+) {
+    let mut model = classifier::load::<B>(model_path, &device);
+    model.describe();
+
+    let records = match valid_path {
+        Some(path) => RawData::new(path).get_all_rows().clone(),
+        None => validation_records(model.as_ref()),
+    };
+    let labels: Vec<u8> = records
+        .iter()
+        .map(|record| {
+            record
+                .survived
+                .expect("threshold tuning data must have a Survived column")
+        })
+        .collect();
+    let probabilities = model.predict_proba(&records);
+
+    let (threshold, _) = tune(&probabilities, &labels, objective);
+    for threshold in [DEFAULT_THRESHOLD, threshold] {
+        let metrics = Metrics::new(&probabilities, &labels, threshold);
+        println!(
+            "*** [Threshold {:.3}] Accuracy {:.3} % | {} {:.3}",
+            threshold,
+            metrics.accuracy * 100.0,
+            objective.name(),
+            objective.value(&metrics.confusion)
+        );
+        println!("    {}", metrics.summary());
+    }
+
+    model.set_threshold(Some(threshold));
+    model.save(model_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tunes_the_threshold_for_the_objective() {
+        let (threshold, accuracy) =
+            tune(&[0.1, 0.3, 0.6, 0.7], &[0, 1, 1, 1], &Objective::Accuracy);
+        assert_eq!((threshold, accuracy), (0.3, 1.0));
+    }
+
+    #[test]
+    fn ties_go_to_the_threshold_closest_to_half() {
+        let (threshold, _) = tune(&[0.2, 0.8], &[0, 1], &Objective::Accuracy);
+        assert_eq!(threshold, DEFAULT_THRESHOLD);
+    }
+
+    #[test]
+    fn minimizes_costs() {
+        // a missed survivor costs ten times a false alarm
+        let objective: Objective = "cost:1:10".parse().unwrap();
+        let (threshold, score) = tune(&[0.1, 0.3, 0.6, 0.9], &[0, 1, 0, 1], &objective);
+        assert_eq!((threshold, score), (0.3, -1.0));
+    }
+
+    #[test]
+    fn youden_adds_recall_and_specificity() {
+        let confusion = ConfusionMatrix::new(&[0.1, 0.6, 0.7, 0.8], &[0, 0, 1, 1], 0.5);
+        assert_eq!(Objective::Youden.value(&confusion), 0.5);
+    }
+
+    #[test]
+    fn parses_objectives() {
+        assert_eq!("f1".parse(), Ok(Objective::F1));
+        assert_eq!(
+            "cost".parse(),
+            Ok(Objective::Cost {
+                false_positive: 1.0,
+                false_negative: 1.0
+            })
+        );
+        assert_eq!(
+            "cost:2:5".parse(),
+            Ok(Objective::Cost {
+                false_positive: 2.0,
+                false_negative: 5.0
+            })
+        );
+        assert!("cost:x".parse::<Objective>().is_err());
+        assert!("auc".parse::<Objective>().is_err());
+    }
+}
@@ -1,15 +1,26 @@
-#![allow(dead_code)]
+use std::sync::Arc;
+
 use burn::{
     config::Config,
-    module::{AutodiffModule, Module},
-    optim::{AdamConfig, GradientsParams, Optimizer},
-    record::{FullPrecisionSettings, NamedMpkFileRecorder},
-    tensor::backend::AutodiffBackend,
+    data::dataloader::{DataLoader, DataLoaderBuilder},
+    module::AutodiffModule,
+    optim::{GradientsParams, Optimizer},
+    tensor::{activation::sigmoid, backend::AutodiffBackend, ElementConversion},
 };
 
 use crate::{
-    data::DataSet,
-    model::{Model, ModelConfig},
+    bundle::{ModelBundle, NeuralNet, TrainingMetadata},
+    checkpoint::{Checkpointer, EarlyStoppingConfig, TrainingState},
+    classifier::{Classifier, TrainingData},
+    data::{
+        Batch, DataSet, ImputeConfig, Pipeline, PreprocessConfig, SplitConfig, TitanicBatcher,
+        TitanicRecord,
+    },
+    infer,
+    metrics::Metrics,
+    model::{values, Model, ModelConfig, DEFAULT_THRESHOLD},
+    optimizer::{OptimizerConfig, OptimizerKind},
+    scheduler::{Scheduler, SchedulerConfig},
 };
 
 #[derive(Config)]
@@ -23,56 +34,419 @@ pub struct ExpConfig {
     #[config(default = 42)]
     pub seed: u64,
 
-    pub optimizer: AdamConfig,
+    #[config(default = "OptimizerConfig::new()")]
+    pub optimizer: OptimizerConfig,
+
+    #[config(default = "SchedulerConfig::new()")]
+    pub scheduler: SchedulerConfig,
+
+    // Maximum number of training rows, all of them when unset.
+    pub dataset_size: Option<usize>,
+
+    #[config(default = 8e-3)]
+    pub learning_rate: f64,
+
+    // Set to the dataset size (or larger) for full-batch gradient descent.
+    #[config(default = 64)]
+    pub batch_size: usize,
+
+    // L1 and L2 penalties on the weights of every linear layer, added to the loss.
+    #[config(default = 0.0)]
+    pub l1_penalty: f64,
+
+    #[config(default = 0.0)]
+    pub l2_penalty: f64,
+
+    #[config(default = "ImputeConfig::new()")]
+    pub impute: ImputeConfig,
+
+    #[config(default = "PreprocessConfig::new()")]
+    pub preprocess: PreprocessConfig,
+
+    // Hold out a stratified validation split of the training data instead of reading a
+    // separate validation csv.
+    pub split: Option<SplitConfig>,
+
+    pub early_stopping: Option<EarlyStoppingConfig>,
+}
+
+pub fn experiment_path(model_path: &str) -> String {
+    format!("{}.exp.json", model_path)
+}
+
+pub fn train<B: AutodiffBackend>(
+    model_path: &str,
+    train_paths: &[String],
+    valid_path: &str,
+    config: ExpConfig,
+    model_config: ModelConfig,
+    resume: bool,
+    device: B::Device,
+) {
+    let mut data = TrainingData::load(train_paths, valid_path, config.split.as_ref(), config.seed);
+    if let Some(dataset_size) = config.dataset_size {
+        data.records.truncate(dataset_size);
+    }
+
+    let checkpointer = Checkpointer::new(model_path, resume);
+    let net = NeuralNet::<B>::fit(
+        &data,
+        config.clone(),
+        model_config,
+        true,
+        Some(&checkpointer),
+        &device,
+    );
+
+    infer::print_coefficients(&net.bundle, &net.model);
+    net.save(model_path);
+    config
+        .save(experiment_path(model_path))
+        .expect("could not save experiment config");
+}
+
+impl<B: AutodiffBackend> NeuralNet<B> {
+    /// Fits a fresh model on `data` and bundles it with its pipeline and configs.
+    pub fn fit(
+        data: &TrainingData,
+        config: ExpConfig,
+        model_config: ModelConfig,
+        verbose: bool,
+        checkpointer: Option<&Checkpointer>,
+        device: &B::Device,
+    ) -> NeuralNet<B> {
+        let Fitted {
+            model,
+            model_config,
+            pipeline,
+            epochs,
+            best_epoch,
+            valid_loss,
+            valid_accuracy,
+            ..
+        } = fit::<B>(
+            &data.records,
+            &data.valid_records,
+            &config,
+            model_config,
+            verbose,
+            checkpointer,
+            device,
+        );
+
+        let metadata = TrainingMetadata::new(
+            data.train_path.clone(),
+            data.valid_path.clone(),
+            data.records.len(),
+            epochs,
+            valid_loss,
+            valid_accuracy,
+        )
+        .with_best_epoch(best_epoch);
+        let bundle = ModelBundle::new(model_config, pipeline.names(), pipeline, config, metadata);
+        NeuralNet {
+            bundle,
+            model,
+            device: device.clone(),
+        }
+    }
+}
 
-    #[config(default = 6)]
-    pub input_feature_len: usize,
+pub struct Fitted<B: AutodiffBackend> {
+    pub model: Model<B>,
+    pub model_config: ModelConfig,
+    pub pipeline: Pipeline,
+    // Epochs actually run, fewer than configured after early stopping.
+    pub epochs: usize,
+    // Set when the weights of the best epoch were restored.
+    pub best_epoch: Option<usize>,
+    pub valid_loss: f64,
+    pub valid_accuracy: f64,
+}
 
-    #[config(default = 889)]
-    pub dataset_size: usize,
+/// Fits the feature pipeline on `records` and sizes the model's input and embeddings to it.
+pub fn fit_pipeline(
+    records: &[TitanicRecord],
+    config: &ExpConfig,
+    model_config: &mut ModelConfig,
+) -> Pipeline {
+    for column in model_config.embedded() {
+        assert!(
+            model_config.features.keeps(&column),
+            "cannot embed {0} with the {0} feature disabled",
+            column.name()
+        );
+    }
+    let pipeline = Pipeline::fit(
+        &model_config.features,
+        &model_config.embedded(),
+        &config.impute,
+        &config.preprocess,
+        records,
+    );
+    model_config.feature_size = pipeline.names().len();
+    for (embedding, vocab_size) in model_config
+        .embeddings
+        .iter_mut()
+        .zip(pipeline.encoder.vocab_sizes())
+    {
+        embedding.vocab_size = vocab_size;
+    }
+    pipeline
 }
 
-pub fn train<B: AutodiffBackend>(model_path: &str, device: B::Device) {
-    let optimizer = AdamConfig::new();
-    let config = ExpConfig::new(optimizer);
-    let mut model: Model<B> = ModelConfig::new().init(&device);
+/// Fits the pipeline and a fresh model on `records`, validating on `valid_records` after every
+/// epoch. Shared by `train` and cross-validation.
+pub fn fit<B: AutodiffBackend>(
+    records: &[TitanicRecord],
+    valid_records: &[TitanicRecord],
+    config: &ExpConfig,
+    mut model_config: ModelConfig,
+    verbose: bool,
+    checkpointer: Option<&Checkpointer>,
+    device: &B::Device,
+) -> Fitted<B> {
+    if let Some(early_stopping) = &config.early_stopping {
+        assert!(
+            early_stopping.patience >= 1,
+            "early stopping patience must be at least 1"
+        );
+    }
+
+    // fitted on the training split only, then applied to both
+    let pipeline = fit_pipeline(records, config, &mut model_config);
+    let training_set = DataSet::from_records(records, &pipeline);
+    let test_set = DataSet::from_records(valid_records, &pipeline);
+
+    // seeded first so the initial weights are reproducible too
     B::seed(config.seed);
+    let model: Model<B> = model_config.init(device);
+
+    let dataloader_train = DataLoaderBuilder::new(TitanicBatcher::<B>::new(device.clone()))
+        .batch_size(config.batch_size)
+        .shuffle(config.seed)
+        .num_workers(config.workers)
+        .build(training_set);
+    let dataloader_test =
+        DataLoaderBuilder::new(TitanicBatcher::<B::InnerBackend>::new(device.clone()))
+            .batch_size(config.batch_size)
+            .num_workers(config.workers)
+            .build(test_set);
+
+    let run = Run {
+        config,
+        model_config: &model_config,
+        steps_per_epoch: records.len().div_ceil(config.batch_size),
+        dataloader_train,
+        dataloader_test,
+        checkpointer,
+        verbose,
+        device,
+    };
+    let optimizer = &config.optimizer;
+    let (model, epochs, best_epoch) = match optimizer.kind {
+        OptimizerKind::Adam => run_epochs(model, optimizer.adam().init(), &run),
+        OptimizerKind::AdamW => run_epochs(model, optimizer.adamw().init(), &run),
+        OptimizerKind::Sgd => run_epochs(model, optimizer.sgd().init(), &run),
+        OptimizerKind::RmsProp => run_epochs(model, optimizer.rmsprop().init(), &run),
+    };
+    let (valid_loss, valid_accuracy, _) = validate(&model, &run.dataloader_test, device);
+
+    Fitted {
+        model,
+        model_config,
+        pipeline,
+        epochs,
+        best_epoch,
+        valid_loss,
+        valid_accuracy,
+    }
+}
+
+/// Everything the epoch loop needs besides the model and the optimizer, whose concrete type
+/// depends on `OptimizerConfig::kind`.
+struct Run<'a, B: AutodiffBackend> {
+    config: &'a ExpConfig,
+    model_config: &'a ModelConfig,
+    steps_per_epoch: usize,
+    dataloader_train: Arc<dyn DataLoader<Batch<B>>>,
+    dataloader_test: Arc<dyn DataLoader<Batch<B::InnerBackend>>>,
+    checkpointer: Option<&'a Checkpointer>,
+    verbose: bool,
+    device: &'a B::Device,
+}
+
+/// Returns the trained model, the number of epochs run and the restored best epoch, if any.
+fn run_epochs<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+    mut model: Model<B>,
+    mut optim: O,
+    run: &Run<B>,
+) -> (Model<B>, usize, Option<usize>) {
+    let Run {
+        config,
+        model_config,
+        steps_per_epoch,
+        dataloader_train,
+        dataloader_test,
+        checkpointer,
+        verbose,
+        device,
+    } = run;
+    let (checkpointer, verbose, device) = (*checkpointer, *verbose, *device);
+
+    let mut scheduler = Scheduler::new(
+        &config.scheduler,
+        config.learning_rate,
+        *steps_per_epoch,
+        config.epochs,
+    );
+    let mut state = TrainingState::new(0);
+    let mut start = 0;
+    let mut best: Option<Model<B>> = None;
 
-    let training_set: DataSet<B> = DataSet::training(&device);
-    let test_set: DataSet<<B as AutodiffBackend>::InnerBackend> = DataSet::testing(&device);
-    let mut optim = config.optimizer.init();
+    if let Some(checkpointer) = checkpointer.filter(|c| !c.resume) {
+        checkpointer.clear();
+    }
+    if let Some(checkpointer) = checkpointer.filter(|c| c.resume) {
+        if let Some(saved) = checkpointer.state() {
+            model = checkpointer.load_model(saved.epoch, model, device);
+            optim = checkpointer.load_optimizer(saved.epoch, optim, device);
+            best = saved
+                .best_epoch
+                .map(|epoch| checkpointer.load_model(epoch, model_config.init(device), device));
+            if let Some(scheduler_state) = saved.scheduler.clone() {
+                scheduler.restore(scheduler_state);
+            }
+            start = saved.epoch + 1;
+            if verbose {
+                println!("Resuming after epoch {}", saved.epoch);
+            }
+            state = saved;
+        }
+    }
 
-    for epoch in 0..10 {
+    let mut step = start * steps_per_epoch;
+    let mut epochs = start;
+    for epoch in start..config.epochs {
         // training
-        let batch = training_set.batch();
-        let output = model.forward_step(&batch, &device);
-
-        println!(
-            "[Train - Epoch {}] Loss {:.3} | Accuracy {:.3} %",
-            epoch,
-            output.loss.clone().into_scalar(),
-            output.accuracy
-        );
+        let mut loss = 0.0;
+        let mut accuracy = 0.0;
+        let mut items = 0;
+        let mut lr = scheduler.lr(step);
+        for batch in dataloader_train.iter() {
+            let size = batch.labels.dims()[0];
+            let output = model.forward_step(&batch, DEFAULT_THRESHOLD, device);
+
+            loss += output.loss.clone().into_scalar().elem::<f64>() * size as f64;
+            accuracy += output.accuracy as f64 * size as f64;
+            items += size;
 
-        let grads = output.loss.backward();
-        let grads = GradientsParams::from_grads(grads, &model);
-        model = optim.step(8e-3, model, grads);
+            lr = scheduler.lr(step);
+            step += 1;
+            let mut objective = output.loss;
+            if config.l1_penalty > 0.0 || config.l2_penalty > 0.0 {
+                objective = objective + model.penalty(config.l1_penalty, config.l2_penalty);
+            }
+            let grads = objective.backward();
+            let grads = GradientsParams::from_grads(grads, &model);
+            model = optim.step(lr, model, grads);
+        }
+        epochs = epoch + 1;
+
+        if verbose {
+            println!(
+                "[Train - Epoch {}] Loss {:.3} | Accuracy {:.3} % | LR {:.2e}",
+                epoch,
+                loss / items as f64,
+                accuracy / items as f64,
+                lr
+            );
+        }
 
         // validation
-        let model_valid = model.valid();
-        let batch = test_set.batch();
-        let output = model_valid.forward_step(&batch, &device);
-
-        println!(
-            "*** [Validate - Epoch {}] Loss {:.3} | Accuracy {:.3} %",
-            epoch,
-            output.loss.into_scalar(),
-            output.accuracy,
-        );
+        let (valid_loss, valid_accuracy, valid_metrics) = validate(&model, dataloader_test, device);
+        if verbose {
+            println!(
+                "*** [Validate - Epoch {}] Loss {:.3} | Accuracy {:.3} %",
+                epoch, valid_loss, valid_accuracy,
+            );
+            println!("    {}", valid_metrics.summary());
+        }
+        scheduler.end_epoch(valid_loss);
+
+        state.epoch = epoch;
+        state.scheduler = Some(scheduler.state().clone());
+        if let Some(early_stopping) = &config.early_stopping {
+            let value = early_stopping.monitor.value(valid_loss, &valid_metrics);
+            if early_stopping.improved(value, state.best_value) {
+                state.best_epoch = Some(epoch);
+                state.best_value = Some(value);
+                state.stale_epochs = 0;
+                best = Some(model.clone());
+            } else {
+                state.stale_epochs += 1;
+            }
+        }
+        if let Some(checkpointer) = checkpointer {
+            checkpointer.save(&model, &optim, &state);
+        }
+
+        if let Some(early_stopping) = &config.early_stopping {
+            if state.stale_epochs >= early_stopping.patience {
+                if verbose {
+                    println!(
+                        "Early stopping after epoch {}, no improvement since epoch {}",
+                        epoch,
+                        state.best_epoch.unwrap_or(0)
+                    );
+                }
+                break;
+            }
+        }
+    }
+
+    let restore_best = config
+        .early_stopping
+        .as_ref()
+        .map(|e| e.restore_best)
+        .unwrap_or(false);
+    match best {
+        Some(best) if restore_best => {
+            if verbose {
+                println!("Restoring the best epoch {}", state.best_epoch.unwrap_or(0));
+            }
+            (best, epochs, state.best_epoch)
+        }
+        _ => (model, epochs, None),
+    }
+}
+
+/// Size weighted loss and accuracy, plus the full metrics, over the validation set.
+fn validate<B: AutodiffBackend>(
+    model: &Model<B>,
+    dataloader: &Arc<dyn DataLoader<Batch<B::InnerBackend>>>,
+    device: &B::Device,
+) -> (f64, f64, Metrics) {
+    let model_valid = model.valid();
+    let mut loss = 0.0;
+    let mut accuracy = 0.0;
+    let mut items = 0;
+    let mut probabilities = Vec::new();
+    let mut labels = Vec::new();
+    for batch in dataloader.iter() {
+        let size = batch.labels.dims()[0];
+        let output = model_valid.forward_step(&batch, DEFAULT_THRESHOLD, device);
+
+        loss += output.loss.into_scalar().elem::<f64>() * size as f64;
+        accuracy += output.accuracy as f64 * size as f64;
+        items += size;
+        probabilities.extend(values(sigmoid(output.output)));
+        labels.extend(values(batch.labels).into_iter().map(|label| label as u8));
     }
 
-    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
-    model
-        .save_file(model_path, &recorder)
-        .expect("could not save model");
+    (
+        loss / items as f64,
+        accuracy / items as f64,
+        Metrics::new(&probabilities, &labels, DEFAULT_THRESHOLD),
+    )
 }
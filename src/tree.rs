@@ -0,0 +1,444 @@
+use std::str::FromStr;
+
+use burn::config::Config;
+use rand::{rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    classifier::TrainingData,
+    data::{DataSet, FeatureConfig, ImputeConfig, Pipeline, PreprocessConfig, TitanicRecord},
+    metrics::Metrics,
+    model::DEFAULT_THRESHOLD,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Node {
+    // Survival probability for CART, a log-odds increment for boosted trees.
+    Leaf {
+        value: f32,
+        #[serde(default)]
+        samples: usize,
+    },
+    // Rows with `feature <= threshold` go left.
+    Split {
+        feature: usize,
+        threshold: f32,
+        gain: f32,
+        left: usize,
+        right: usize,
+    },
+}
+
+/// Nodes of one tree, the root first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tree {
+    pub nodes: Vec<Node>,
+}
+
+impl Tree {
+    pub fn predict(&self, row: &[f32]) -> f32 {
+        let mut i = 0;
+        loop {
+            match &self.nodes[i] {
+                Node::Leaf { value, .. } => return *value,
+                Node::Split {
+                    feature,
+                    threshold,
+                    left,
+                    right,
+                    ..
+                } => {
+                    i = if row[*feature] <= *threshold {
+                        *left
+                    } else {
+                        *right
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the split gain of every feature to `importance`.
+    pub fn add_importance(&self, importance: &mut [f64]) {
+        for node in &self.nodes {
+            if let Node::Split { feature, gain, .. } = node {
+                importance[*feature] += *gain as f64;
+            }
+        }
+    }
+
+    /// The tree as nested if/else rules over the feature `names`, leaves showing the
+    /// survival probability and number of training passengers.
+    pub fn rules(&self, names: &[String]) -> String {
+        let mut rules = String::new();
+        self.write_rules(0, 0, names, &mut rules);
+        rules
+    }
+
+    fn write_rules(&self, i: usize, depth: usize, names: &[String], rules: &mut String) {
+        let indent = "    ".repeat(depth);
+        match &self.nodes[i] {
+            Node::Leaf { value, samples } => {
+                let outcome = if *value >= 0.5 { "survived" } else { "died" };
+                rules.push_str(&format!(
+                    "{}{} (p = {:.2}, {} passengers)\n",
+                    indent, outcome, value, samples
+                ));
+            }
+            Node::Split {
+                feature,
+                threshold,
+                left,
+                right,
+                ..
+            } => {
+                rules.push_str(&format!(
+                    "{}if {} <= {:.2}:\n",
+                    indent, names[*feature], threshold
+                ));
+                self.write_rules(*left, depth + 1, names, rules);
+                rules.push_str(&format!("{}else:\n", indent));
+                self.write_rules(*right, depth + 1, names, rules);
+            }
+        }
+    }
+}
+
+/// Impurity CART minimizes at every split.
+#[derive(Config, Debug, PartialEq)]
+pub enum Criterion {
+    Gini,
+    Entropy,
+}
+
+impl FromStr for Criterion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gini" => Ok(Criterion::Gini),
+            "entropy" => Ok(Criterion::Entropy),
+            _ => Err(format!("unknown criterion {}, expected gini or entropy", s)),
+        }
+    }
+}
+
+impl Criterion {
+    /// Impurity of a node where a fraction `p` of the passengers survived.
+    fn impurity(&self, p: f64) -> f64 {
+        match self {
+            Criterion::Gini => 2.0 * p * (1.0 - p),
+            Criterion::Entropy => [p, 1.0 - p]
+                .iter()
+                .filter(|q| **q > 0.0)
+                .map(|q| -q * q.log2())
+                .sum(),
+        }
+    }
+}
+
+/// Features considered at every split.
+#[derive(Config, Debug, PartialEq)]
+pub enum MaxFeatures {
+    All,
+    Sqrt,
+    Log2,
+    Count(usize),
+}
+
+impl FromStr for MaxFeatures {
+    type Err = String;
+
+    /// `all`, `sqrt`, `log2` or a number of features.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(MaxFeatures::All),
+            "sqrt" => Ok(MaxFeatures::Sqrt),
+            "log2" => Ok(MaxFeatures::Log2),
+            _ => s.parse().map(MaxFeatures::Count).map_err(|_| {
+                format!(
+                    "invalid max features {}, expected all, sqrt, log2 or a number",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+impl MaxFeatures {
+    fn count(&self, features: usize) -> usize {
+        let count = match self {
+            MaxFeatures::All => features,
+            MaxFeatures::Sqrt => (features as f64).sqrt().round() as usize,
+            MaxFeatures::Log2 => (features as f64).log2().round() as usize,
+            MaxFeatures::Count(count) => *count,
+        };
+        count.clamp(1, features.max(1))
+    }
+}
+
+/// A CART classification tree with exact splits between distinct feature values.
+#[derive(Config, Debug)]
+pub struct TreeConfig {
+    #[config(default = "Criterion::Gini")]
+    pub criterion: Criterion,
+
+    #[config(default = 6)]
+    pub max_depth: usize,
+
+    // Nodes with fewer rows are not split.
+    #[config(default = 2)]
+    pub min_samples_split: usize,
+
+    #[config(default = 1)]
+    pub min_samples_leaf: usize,
+
+    // Random forests look at a random subset of the features at every split.
+    #[config(default = "MaxFeatures::All")]
+    pub max_features: MaxFeatures,
+}
+
+struct CartSplit {
+    feature: usize,
+    threshold: f32,
+    gain: f64,
+}
+
+struct Cart<'a> {
+    config: &'a TreeConfig,
+    rows: &'a [Vec<f32>],
+    labels: &'a [f32],
+    nodes: Vec<Node>,
+}
+
+impl Cart<'_> {
+    fn survival_rate(&self, sample: &[usize]) -> f64 {
+        sample.iter().map(|&i| self.labels[i] as f64).sum::<f64>() / sample.len() as f64
+    }
+
+    fn best_split(&self, sample: &[usize], rng: &mut StdRng) -> Option<CartSplit> {
+        let features = self.rows[0].len();
+        let mut candidates: Vec<usize> = (0..features).collect();
+        candidates.shuffle(rng);
+        candidates.truncate(self.config.max_features.count(features));
+
+        let n = sample.len() as f64;
+        let positives = sample.iter().map(|&i| self.labels[i] as f64).sum::<f64>();
+        let parent = self.config.criterion.impurity(positives / n);
+        let min_leaf = self.config.min_samples_leaf.max(1);
+        let mut best: Option<CartSplit> = None;
+
+        for feature in candidates {
+            let mut sorted = sample.to_vec();
+            sorted.sort_by(|a, b| self.rows[*a][feature].total_cmp(&self.rows[*b][feature]));
+
+            let mut left_positives = 0.0;
+            for k in 1..sorted.len() {
+                left_positives += self.labels[sorted[k - 1]] as f64;
+                let (low, high) = (
+                    self.rows[sorted[k - 1]][feature],
+                    self.rows[sorted[k]][feature],
+                );
+                if low == high || k < min_leaf || sorted.len() - k < min_leaf {
+                    continue;
+                }
+
+                let (left, right) = (k as f64, n - k as f64);
+                let impurity = (left * self.config.criterion.impurity(left_positives / left)
+                    + right
+                        * self
+                            .config
+                            .criterion
+                            .impurity((positives - left_positives) / right))
+                    / n;
+                let gain = (parent - impurity) * n;
+                if gain > 1e-12 && best.as_ref().is_none_or(|b| gain > b.gain) {
+                    best = Some(CartSplit {
+                        feature,
+                        threshold: (low + high) / 2.0,
+                        gain,
+                    });
+                }
+            }
+        }
+        best
+    }
+
+    fn grow(&mut self, sample: Vec<usize>, depth: usize, rng: &mut StdRng) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(Node::Leaf {
+            value: self.survival_rate(&sample) as f32,
+            samples: sample.len(),
+        });
+        if depth >= self.config.max_depth || sample.len() < self.config.min_samples_split {
+            return node;
+        }
+        let Some(split) = self.best_split(&sample, rng) else {
+            return node;
+        };
+
+        let (left_sample, right_sample): (Vec<usize>, Vec<usize>) = sample
+            .iter()
+            .partition(|&&i| self.rows[i][split.feature] <= split.threshold);
+        let left = self.grow(left_sample, depth + 1, rng);
+        let right = self.grow(right_sample, depth + 1, rng);
+        self.nodes[node] = Node::Split {
+            feature: split.feature,
+            threshold: split.threshold,
+            gain: split.gain as f32,
+            left,
+            right,
+        };
+        node
+    }
+}
+
+/// Grows a CART tree on the `sample` rows, which may repeat for a bootstrap sample.
+pub fn grow(
+    rows: &[Vec<f32>],
+    labels: &[f32],
+    sample: Vec<usize>,
+    config: &TreeConfig,
+    rng: &mut StdRng,
+) -> Tree {
+    let mut cart = Cart {
+        config,
+        rows,
+        labels,
+        nodes: Vec::new(),
+    };
+    cart.grow(sample, 0, rng);
+    Tree { nodes: cart.nodes }
+}
+
+/// Feature rows and labels of the training and validation data, the pipeline being fitted on
+/// the training rows only.
+pub struct Matrices {
+    pub pipeline: Pipeline,
+    pub rows: Vec<Vec<f32>>,
+    pub labels: Vec<f32>,
+    pub valid_rows: Vec<Vec<f32>>,
+    pub valid_labels: Vec<f32>,
+}
+
+fn matrix(records: &[TitanicRecord], pipeline: &Pipeline) -> (Vec<Vec<f32>>, Vec<f32>) {
+    DataSet::from_records(records, pipeline)
+        .data
+        .into_iter()
+        .map(|point| (point.feature, point.label))
+        .unzip()
+}
+
+pub fn matrices(
+    data: &TrainingData,
+    features: &FeatureConfig,
+    impute: &ImputeConfig,
+    preprocess: &PreprocessConfig,
+) -> Matrices {
+    let pipeline = Pipeline::fit(features, &[], impute, preprocess, &data.records);
+    let (rows, labels) = matrix(&data.records, &pipeline);
+    let (valid_rows, valid_labels) = matrix(&data.valid_records, &pipeline);
+    Matrices {
+        pipeline,
+        rows,
+        labels,
+        valid_rows,
+        valid_labels,
+    }
+}
+
+/// Validation metrics of a tree model, printing the loss, accuracy and summary when `verbose`.
+pub fn validate(name: &str, probabilities: &[f32], labels: &[f32], verbose: bool) -> Metrics {
+    let labels: Vec<u8> = labels.iter().map(|l| *l as u8).collect();
+    let metrics = Metrics::new(probabilities, &labels, DEFAULT_THRESHOLD);
+    if !verbose {
+        return metrics;
+    }
+    println!(
+        "*** [Validate - {}] Loss {:.3} | Accuracy {:.3} %",
+        name,
+        metrics.log_loss,
+        metrics.accuracy * 100.0
+    );
+    println!("    {}", metrics.summary());
+    metrics
+}
+
+/// Prints the features with the largest total split gain.
+pub fn print_importance(names: &[String], importance: &[f64]) {
+    let mut ranked: Vec<(&String, f64)> = names.iter().zip(importance.iter().copied()).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    println!("  {:<24} {:>10}", "feature", "gain");
+    for (name, gain) in ranked.iter().take(10) {
+        println!("  {:<24} {:>10.3}", name, gain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    // Survival follows the second feature, the first one is noise.
+    fn data() -> (Vec<Vec<f32>>, Vec<f32>) {
+        let rows = vec![
+            vec![1.0, 1.0],
+            vec![0.0, 2.0],
+            vec![1.0, 3.0],
+            vec![0.0, 4.0],
+        ];
+        (rows, vec![0.0, 0.0, 1.0, 1.0])
+    }
+
+    fn fit(config: &TreeConfig) -> Tree {
+        let (rows, labels) = data();
+        let mut rng = StdRng::seed_from_u64(42);
+        grow(&rows, &labels, (0..rows.len()).collect(), config, &mut rng)
+    }
+
+    #[test]
+    fn splits_between_distinct_values_of_the_best_feature() {
+        for criterion in [Criterion::Gini, Criterion::Entropy] {
+            let tree = fit(&TreeConfig::new().with_criterion(criterion));
+            assert_eq!(tree.nodes.len(), 3);
+            match tree.nodes[0] {
+                Node::Split {
+                    feature, threshold, ..
+                } => assert_eq!((feature, threshold), (1, 2.5)),
+                Node::Leaf { .. } => panic!("the root should split"),
+            }
+            assert_eq!(tree.predict(&[0.0, 2.0]), 0.0);
+            assert_eq!(tree.predict(&[0.0, 2.6]), 1.0);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_depth_and_leaf_limits() {
+        let stump = fit(&TreeConfig::new().with_max_depth(0));
+        assert_eq!(stump.nodes.len(), 1);
+        assert_eq!(stump.predict(&[0.0, 4.0]), 0.5);
+
+        let large_leaves = fit(&TreeConfig::new().with_min_samples_leaf(3));
+        assert_eq!(large_leaves.nodes.len(), 1);
+    }
+
+    #[test]
+    fn impurity_is_highest_for_even_nodes() {
+        assert_eq!(Criterion::Gini.impurity(0.5), 0.5);
+        assert_eq!(Criterion::Entropy.impurity(0.5), 1.0);
+        assert_eq!(Criterion::Entropy.impurity(0.0), 0.0);
+    }
+
+    #[test]
+    fn max_features_counts() {
+        assert_eq!(MaxFeatures::All.count(16), 16);
+        assert_eq!(MaxFeatures::Sqrt.count(16), 4);
+        assert_eq!(MaxFeatures::Log2.count(16), 4);
+        assert_eq!(MaxFeatures::Count(40).count(16), 16);
+        assert_eq!(MaxFeatures::Count(0).count(16), 1);
+        assert_eq!("sqrt".parse(), Ok(MaxFeatures::Sqrt));
+        assert_eq!("7".parse(), Ok(MaxFeatures::Count(7)));
+        assert!("half".parse::<MaxFeatures>().is_err());
+    }
+}